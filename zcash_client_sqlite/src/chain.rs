@@ -0,0 +1,62 @@
+//! Functions for scanning the chain from a cache of [`CompactBlock`]s.
+//!
+//! [`CompactBlock`]: zcash_client_backend::proto::compact_formats::CompactBlock
+
+use protobuf::Message;
+use rusqlite::params;
+
+use zcash_client_backend::proto::compact_formats::CompactBlock;
+use zcash_primitives::consensus::BlockHeight;
+
+use crate::{error::SqliteClientError, BlockDb};
+
+pub mod init;
+
+/// Calls `with_row` for every cached block between `from_height` (exclusive) and
+/// `from_height + limit` (inclusive), in height order.
+pub fn with_blocks<F>(
+    cache: &BlockDb,
+    from_height: BlockHeight,
+    limit: Option<u32>,
+    mut with_row: F,
+) -> Result<(), SqliteClientError>
+where
+    F: FnMut(CompactBlock) -> Result<(), SqliteClientError>,
+{
+    let mut stmt_blocks = cache.0.prepare(
+        "SELECT height, data FROM compactblocks
+        WHERE height > ?
+        ORDER BY height ASC
+        LIMIT ?",
+    )?;
+
+    let rows = stmt_blocks.query_map(
+        params![
+            u32::from(from_height),
+            limit.unwrap_or(u32::max_value()),
+        ],
+        |row| {
+            let height: u32 = row.get(0)?;
+            let data: Vec<u8> = row.get(1)?;
+            Ok((height, data))
+        },
+    )?;
+
+    for row in rows {
+        let (height, data) = row?;
+        let block = CompactBlock::parse_from_bytes(&data)
+            .map_err(|e| SqliteClientError::CorruptedData(e.to_string()))?;
+
+        if block.height() != BlockHeight::from(height) {
+            return Err(SqliteClientError::CorruptedData(format!(
+                "Block height {} did not match row height {}",
+                block.height(),
+                height
+            )));
+        }
+
+        with_row(block)?;
+    }
+
+    Ok(())
+}
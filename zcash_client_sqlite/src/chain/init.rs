@@ -0,0 +1,20 @@
+//! Functions for initializing the cache database.
+
+use crate::BlockDb;
+
+/// Sets up the internal structure of the cache database, creating the `compactblocks`
+/// table if it does not already exist.
+///
+/// This should be called at wallet startup, before any other operation is performed
+/// against the cache database.
+pub fn init_cache_database(cache: &BlockDb) -> Result<(), rusqlite::Error> {
+    cache.0.execute(
+        "CREATE TABLE IF NOT EXISTS compactblocks (
+            height INTEGER PRIMARY KEY,
+            data BLOB NOT NULL
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
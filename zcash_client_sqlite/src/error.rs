@@ -0,0 +1,84 @@
+//! Error types for problems that may arise when reading or storing wallet data in SQLite.
+
+use std::error;
+use std::fmt;
+
+use zcash_client_backend::data_api::error::Error as DataApiError;
+
+/// The primary error type for the SQLite wallet backend.
+#[derive(Debug)]
+pub enum SqliteClientError {
+    /// A wrapper for rusqlite errors.
+    DbError(rusqlite::Error),
+
+    /// Decoding of a stored value from its serialized form failed.
+    CorruptedData(String),
+
+    /// The rcm value for a note cannot be decoded to a valid note commitment.
+    InvalidNote,
+
+    /// An attempt was made to update a value that does not correspond to a known note.
+    InvalidNoteId,
+
+    /// A Bech32-encoded address or viewing key did not match the expected network.
+    IncorrectHrpExtFvk,
+
+    /// An operation required the data tables to be empty, but they were not.
+    TableNotEmpty,
+
+    /// The wallet database's `schema_version` is newer than this version of the library knows
+    /// how to work with.
+    UnsupportedSchemaVersion(u32, u32),
+
+    /// Wraps errors originating from the `zcash_client_backend` data access API.
+    BackendError(DataApiError<u32>),
+
+    /// Checking out a connection from the read-only connection pool failed.
+    PoolError(r2d2::Error),
+}
+
+impl error::Error for SqliteClientError {}
+
+impl fmt::Display for SqliteClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SqliteClientError::DbError(e) => write!(f, "{}", e),
+            SqliteClientError::CorruptedData(reason) => {
+                write!(f, "Data DB is corrupted: {}", reason)
+            }
+            SqliteClientError::InvalidNote => write!(f, "Invalid note"),
+            SqliteClientError::InvalidNoteId => {
+                write!(f, "The note ID associated with an output is invalid")
+            }
+            SqliteClientError::IncorrectHrpExtFvk => {
+                write!(f, "Incorrect HRP for extended full viewing key")
+            }
+            SqliteClientError::TableNotEmpty => write!(f, "Table is not empty"),
+            SqliteClientError::UnsupportedSchemaVersion(have, want) => write!(
+                f,
+                "The wallet database has schema version {}, but this version of the library only understands up to version {}. Please upgrade.",
+                have, want
+            ),
+            SqliteClientError::BackendError(e) => write!(f, "{}", e),
+            SqliteClientError::PoolError(e) => write!(f, "Failed to check out a pooled connection: {}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for SqliteClientError {
+    fn from(e: rusqlite::Error) -> Self {
+        SqliteClientError::DbError(e)
+    }
+}
+
+impl From<DataApiError<u32>> for SqliteClientError {
+    fn from(e: DataApiError<u32>) -> Self {
+        SqliteClientError::BackendError(e)
+    }
+}
+
+impl From<r2d2::Error> for SqliteClientError {
+    fn from(e: r2d2::Error) -> Self {
+        SqliteClientError::PoolError(e)
+    }
+}
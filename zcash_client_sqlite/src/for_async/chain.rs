@@ -0,0 +1,69 @@
+//! Async wrapper around the block cache database, paralleling [`crate::chain`].
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use zcash_client_backend::proto::compact_formats::CompactBlock;
+use zcash_primitives::consensus::BlockHeight;
+
+use crate::error::SqliteClientError;
+use crate::for_async::async_blocking;
+use crate::{chain, BlockDb};
+
+/// A wrapper for an async connection to the SQLite cache database of [`CompactBlock`]s.
+#[derive(Clone)]
+pub struct BlockDbAsync {
+    inner: Arc<Mutex<BlockDb>>,
+}
+
+impl BlockDbAsync {
+    pub(crate) fn inner(&self) -> Arc<Mutex<BlockDb>> {
+        self.inner.clone()
+    }
+
+    /// Construct a connection to the cache database stored at the specified path.
+    pub fn for_path<P: AsRef<Path>>(path: P) -> Result<Self, rusqlite::Error> {
+        Ok(Self {
+            inner: Arc::new(Mutex::new(BlockDb::for_path(path)?)),
+        })
+    }
+
+    /// Construct a connection to the cache database stored at the specified path,
+    /// encrypted at rest via SQLCipher with the given passphrase.
+    pub fn for_path_encrypted<P: AsRef<Path>>(
+        path: P,
+        key: &str,
+    ) -> Result<Self, SqliteClientError> {
+        Ok(Self {
+            inner: Arc::new(Mutex::new(BlockDb::for_path_encrypted(path, key)?)),
+        })
+    }
+
+    /// Rotates the passphrase protecting this database.
+    pub async fn rekey(&self, new_key: &str) -> Result<(), SqliteClientError> {
+        let db = self.inner.clone();
+        let new_key = new_key.to_string();
+        async_blocking(move || db.lock().unwrap().rekey(&new_key)).await
+    }
+
+    /// Async equivalent of [`crate::chain::with_blocks`]: calls `with_row` for every
+    /// cached block between `from_height` (exclusive) and `from_height + limit`
+    /// (inclusive), in height order, so that a caller can drive a scan loop entirely
+    /// through this crate's async API.
+    pub async fn with_blocks<F>(
+        &self,
+        from_height: BlockHeight,
+        limit: Option<u32>,
+        with_row: F,
+    ) -> Result<(), SqliteClientError>
+    where
+        F: FnMut(CompactBlock) -> Result<(), SqliteClientError> + Send + 'static,
+    {
+        let db = self.inner.clone();
+        async_blocking(move || {
+            let db = db.lock().unwrap();
+            chain::with_blocks(&db, from_height, limit, with_row)
+        })
+        .await
+    }
+}
@@ -0,0 +1,51 @@
+//! Async wrappers around the data and cache database setup routines in
+//! [`crate::wallet::init`] and [`crate::chain::init`].
+
+use zcash_client_backend::wallet::AccountId;
+use zcash_primitives::{consensus, zip32::ExtendedFullViewingKey};
+
+use crate::error::SqliteClientError;
+use crate::for_async::chain::BlockDbAsync;
+use crate::for_async::{async_blocking, WalletDbAsync};
+use crate::wallet;
+
+/// Async equivalent of [`crate::wallet::init::init_wallet_db`].
+pub async fn init_wallet_db<P: consensus::Parameters + Send + Sync + 'static>(
+    wdb: &WalletDbAsync<P>,
+) -> Result<(), SqliteClientError> {
+    let wdb = wdb.clone();
+    async_blocking(move || {
+        let db = wdb.inner.lock().unwrap();
+        wallet::init::init_wallet_db(&db)
+    })
+    .await
+}
+
+/// Async equivalent of [`crate::wallet::init::init_accounts_table`].
+pub async fn init_accounts_table<P: consensus::Parameters + Send + Sync + 'static>(
+    wdb: &WalletDbAsync<P>,
+    extfvks: &[ExtendedFullViewingKey],
+) -> Result<(), SqliteClientError> {
+    let wdb = wdb.clone();
+    let extfvks = extfvks.to_vec();
+    async_blocking(move || {
+        let db = wdb.inner.lock().unwrap();
+        wallet::init::init_accounts_table(&db, &extfvks)
+    })
+    .await
+}
+
+/// Async equivalent of [`crate::chain::init::init_cache_database`].
+pub async fn init_cache_database(cache: &BlockDbAsync) -> Result<(), rusqlite::Error> {
+    let cache = cache.inner();
+    async_blocking(move || crate::chain::init::init_cache_database(&cache.lock().unwrap())).await
+}
+
+/// Returns the account ID that was most recently added to the wallet, if any.
+pub async fn last_account_id<P: consensus::Parameters + Send + Sync + 'static>(
+    wdb: &WalletDbAsync<P>,
+) -> Result<Option<AccountId>, SqliteClientError> {
+    use zcash_client_backend::data_api::WalletRead;
+    let keys = wdb.get_extended_full_viewing_keys().await?;
+    Ok(keys.keys().max_by_key(|a| a.0).copied())
+}
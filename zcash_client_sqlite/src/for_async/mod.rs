@@ -1,3 +1,4 @@
+pub mod chain;
 pub mod init;
 pub mod wallet_actions;
 
@@ -28,28 +29,119 @@ where
 }
 
 use crate::error::SqliteClientError;
-use crate::{wallet, NoteId, WalletDb};
-use rusqlite::Connection;
+use crate::{wallet, ConnHandle, NoteId, WalletDb};
+use r2d2_sqlite::SqliteConnectionManager;
 use std::sync::{Arc, Mutex};
 
 use zcash_primitives::consensus;
 
 /// A wrapper for the SQLite connection to the wallet database.
+///
+/// Reads are served from a pool of read-only connections opened against the database
+/// in WAL journal mode, so that concurrent readers (e.g. several accounts being scanned
+/// at once) each run their queries on their own connection rather than on `inner`, the
+/// single writer connection that [`WalletWrite`] methods use exclusively. Every
+/// [`WalletRead`] method below checks out its own pooled connection and runs on the
+/// blocking pool; before doing so it takes `inner`'s lock just long enough to copy the
+/// network parameters and `max_reorg_depth`, so a read started while a write holds that
+/// lock waits for the write to release it.
 #[derive(Clone)]
 pub struct WalletDbAsync<P> {
     inner: Arc<Mutex<WalletDb<P>>>,
+    read_pool: Arc<r2d2::Pool<SqliteConnectionManager>>,
 }
 
-impl<P: consensus::Parameters> WalletDbAsync<P> {
+/// Rejects the special `:memory:` SQLite path, which [`WalletDbAsync`]'s read pool can't
+/// support: every pooled connection would open its own independent, empty in-memory
+/// database rather than sharing the single writer's.
+fn reject_in_memory_path(path: &Path) -> Result<(), SqliteClientError> {
+    if path == Path::new(":memory:") {
+        return Err(SqliteClientError::CorruptedData(
+            "WalletDbAsync does not support the \":memory:\" path; use WalletDb::for_path \
+             directly instead"
+                .to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+impl<P: consensus::Parameters + Clone> WalletDbAsync<P> {
     pub fn inner(&self) -> Arc<Mutex<WalletDb<P>>> {
         self.inner.clone()
     }
 
-    /// Construct a connection to the wallet database stored at the specified path.
-    pub fn for_path<F: AsRef<Path>>(path: F, params: P) -> Result<Self, rusqlite::Error> {
-        let db = Connection::open(path).map(move |conn| WalletDb { conn, params })?;
+    /// See [`WalletDb::max_reorg_depth`].
+    pub fn max_reorg_depth(&self) -> u32 {
+        self.inner.lock().unwrap().max_reorg_depth()
+    }
+
+    /// See [`WalletDb::set_max_reorg_depth`].
+    pub fn set_max_reorg_depth(&self, max_reorg_depth: u32) {
+        self.inner.lock().unwrap().set_max_reorg_depth(max_reorg_depth)
+    }
+
+    /// Construct a connection to the wallet database stored at the specified path,
+    /// opening it in WAL journal mode and starting up a pool of read-only connections
+    /// alongside the single writer connection.
+    ///
+    /// `:memory:` is not supported here: each connection in the read pool would open its
+    /// own private, empty in-memory database rather than sharing the writer's, so every
+    /// [`WalletRead`] call would silently see no data. Use [`WalletDb::for_path`] directly
+    /// (without a read pool) for in-memory databases, e.g. in tests.
+    pub fn for_path<F: AsRef<Path>>(path: F, params: P) -> Result<Self, SqliteClientError> {
+        reject_in_memory_path(path.as_ref())?;
+
+        let writer = WalletDb::for_path(&path, params.clone())?;
+
+        let manager = SqliteConnectionManager::file(path.as_ref())
+            .with_flags(rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX);
+        let read_pool = r2d2::Pool::builder().build(manager)?;
+
         Ok(Self {
-            inner: Arc::new(Mutex::new(db)),
+            inner: Arc::new(Mutex::new(writer)),
+            read_pool: Arc::new(read_pool),
+        })
+    }
+
+    /// Construct a connection to the wallet database stored at the specified path,
+    /// encrypted at rest via SQLCipher with the given passphrase. The read-only pool's
+    /// connections are keyed on check-out, via [`SqliteConnectionManager::with_init`], so
+    /// every pooled reader can decrypt the database just as the writer does.
+    ///
+    /// `:memory:` is not supported; see [`Self::for_path`].
+    pub fn for_path_encrypted<F: AsRef<Path>>(
+        path: F,
+        key: &str,
+        params: P,
+    ) -> Result<Self, SqliteClientError> {
+        reject_in_memory_path(path.as_ref())?;
+
+        let writer = WalletDb::for_path_encrypted(&path, key, params.clone())?;
+
+        let init_key = key.to_string();
+        let manager = SqliteConnectionManager::file(path.as_ref())
+            .with_flags(rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX)
+            .with_init(move |conn| crate::set_encryption_key(conn, &init_key));
+        let read_pool = r2d2::Pool::builder().build(manager)?;
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(writer)),
+            read_pool: Arc::new(read_pool),
+        })
+    }
+
+    /// Checks out a connection from the read-only pool and wraps it as a [`WalletDb`]
+    /// so the synchronous helpers in [`crate::wallet`] can be reused unchanged.
+    fn checkout_reader(&self) -> Result<WalletDb<P>, SqliteClientError> {
+        let conn = self.read_pool.get()?;
+        let (params, max_reorg_depth) = {
+            let writer = self.inner.lock().unwrap();
+            (writer.params.clone(), writer.max_reorg_depth)
+        };
+        Ok(WalletDb {
+            conn: ConnHandle::Pooled(conn),
+            params,
+            max_reorg_depth,
         })
     }
 
@@ -63,8 +155,139 @@ impl<P: consensus::Parameters> WalletDbAsync<P> {
     }
 }
 
+impl<P: consensus::Parameters + Clone + Send + Sync + 'static> WalletDbAsync<P> {
+    /// Returns the transparent address tracked for `account`, deriving and persisting a
+    /// fresh one from `seed` if none has been stored yet.
+    pub async fn get_or_create_transparent_address(
+        &self,
+        seed: Vec<u8>,
+        account: AccountId,
+    ) -> Result<zcash_primitives::legacy::TransparentAddress, SqliteClientError> {
+        let db = self.clone();
+        async_blocking(move || {
+            // This may insert a row, so it needs the writer rather than a read-only
+            // pooled connection.
+            let db = db.inner.lock().unwrap();
+            wallet::transparent::get_or_create_transparent_address(&db, &seed, account)
+        })
+        .await
+    }
+
+    /// Returns the transparent address tracked for `account`, if any.
+    pub async fn get_transparent_address(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<zcash_primitives::legacy::TransparentAddress>, SqliteClientError> {
+        let db = self.clone();
+        async_blocking(move || {
+            let db = db.checkout_reader()?;
+            wallet::transparent::get_transparent_address(&db, account)
+        })
+        .await
+    }
+
+    /// Returns the total value of unspent transparent outputs tracked for `account` as
+    /// of `anchor_height`.
+    pub async fn get_transparent_balance(
+        &self,
+        account: AccountId,
+        anchor_height: BlockHeight,
+    ) -> Result<Amount, SqliteClientError> {
+        let db = self.clone();
+        async_blocking(move || {
+            let db = db.checkout_reader()?;
+            wallet::transparent::get_transparent_balance(&db, account, anchor_height)
+        })
+        .await
+    }
+
+    /// Returns every unspent transparent output tracked for `account` as of
+    /// `anchor_height`.
+    pub async fn get_utxos(
+        &self,
+        account: AccountId,
+        anchor_height: BlockHeight,
+    ) -> Result<Vec<wallet::transparent::Utxo>, SqliteClientError> {
+        let db = self.clone();
+        async_blocking(move || {
+            let db = db.checkout_reader()?;
+            wallet::transparent::get_utxos(&db, account, anchor_height)
+        })
+        .await
+    }
+
+    /// Returns every unspent transparent output paying `address` as of `anchor_height`,
+    /// so a caller assembling an autoshielding transaction can select them as inputs.
+    pub async fn get_unspent_transparent_outputs(
+        &self,
+        address: zcash_primitives::legacy::TransparentAddress,
+        anchor_height: BlockHeight,
+    ) -> Result<Vec<wallet::transparent::Utxo>, SqliteClientError> {
+        let db = self.clone();
+        async_blocking(move || {
+            let db = db.checkout_reader()?;
+            wallet::transparent::get_unspent_transparent_outputs(&db, &address, anchor_height)
+        })
+        .await
+    }
+
+    /// Returns the diversified address `account` would currently hand out, without
+    /// advancing its stored diversifier index.
+    pub async fn get_current_diversified_address(
+        &self,
+        account: AccountId,
+    ) -> Result<PaymentAddress, SqliteClientError> {
+        let db = self.clone();
+        async_blocking(move || {
+            let db = db.checkout_reader()?;
+            wallet::diversified::get_current_diversified_address(&db, account)
+        })
+        .await
+    }
+
+    /// Derives, persists, and returns a fresh diversified address for `account`.
+    pub async fn get_next_diversified_address(
+        &self,
+        account: AccountId,
+    ) -> Result<PaymentAddress, SqliteClientError> {
+        let db = self.clone();
+        async_blocking(move || {
+            // This updates the stored diversifier index, so it needs the writer rather
+            // than a read-only pooled connection.
+            let db = db.inner.lock().unwrap();
+            wallet::diversified::get_next_diversified_address(&db, account)
+        })
+        .await
+    }
+
+    /// Returns display metadata for the transaction referenced by `tx_ref`, the `TxRef`
+    /// returned from [`WalletWrite::store_sent_tx`]/[`WalletWrite::store_received_tx`].
+    pub async fn get_tx_info(
+        &self,
+        tx_ref: i64,
+    ) -> Result<wallet::TransactionInfo, SqliteClientError> {
+        let db = self.clone();
+        async_blocking(move || {
+            let db = db.checkout_reader()?;
+            wallet::get_tx_info(&db, tx_ref)
+        })
+        .await
+    }
+
+    /// Rotates the passphrase protecting this database. `PRAGMA rekey` re-encrypts the
+    /// database file itself, so every other live connection -- including those already
+    /// checked out of the read-only pool -- is left holding a now-stale key; callers must
+    /// drop this `WalletDbAsync` and reconnect with [`Self::for_path_encrypted`] and the
+    /// new key afterwards.
+    pub async fn rekey(&self, new_key: &str) -> Result<(), SqliteClientError> {
+        let db = self.inner.clone();
+        let new_key = new_key.to_string();
+        async_blocking(move || db.lock().unwrap().rekey(&new_key)).await
+    }
+}
+
 #[async_trait::async_trait]
-impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAsync<P> {
+impl<P: consensus::Parameters + Clone + Send + Sync + 'static> WalletRead for WalletDbAsync<P> {
     type Error = SqliteClientError;
     type NoteRef = NoteId;
     type TxRef = i64;
@@ -74,7 +297,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
     ) -> Result<Option<(BlockHeight, BlockHeight)>, Self::Error> {
         let db = self.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             wallet::block_height_extrema(&db).map_err(SqliteClientError::from)
         })
         .await
@@ -86,7 +309,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
     ) -> Result<Option<BlockHash>, Self::Error> {
         let db = self.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             wallet::get_block_hash(&db, block_height).map_err(SqliteClientError::from)
         })
         .await
@@ -95,7 +318,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
     async fn get_tx_height(&self, txid: TxId) -> Result<Option<BlockHeight>, Self::Error> {
         let db = self.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             wallet::get_tx_height(&db, txid).map_err(SqliteClientError::from)
         })
         .await
@@ -104,7 +327,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
     async fn get_address(&self, account: AccountId) -> Result<Option<PaymentAddress>, Self::Error> {
         let db = self.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             wallet::get_address(&db, account).map_err(SqliteClientError::from)
         })
         .await
@@ -115,7 +338,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
     ) -> Result<HashMap<AccountId, ExtendedFullViewingKey>, Self::Error> {
         let db = self.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             wallet::get_extended_full_viewing_keys(&db).map_err(SqliteClientError::from)
         })
         .await
@@ -129,7 +352,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
         let db = self.clone();
         let extfvk = extfvk.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             wallet::is_valid_account_extfvk(&db, account, &extfvk)
         })
         .await
@@ -142,7 +365,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
     ) -> Result<Amount, Self::Error> {
         let db = self.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             wallet::get_balance_at(&db, account, anchor_height)
         })
         .await
@@ -151,7 +374,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
     async fn get_memo(&self, id_note: Self::NoteRef) -> Result<Memo, Self::Error> {
         let db = self.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             match id_note {
                 NoteId::SentNoteId(id_note) => wallet::get_sent_memo(&db, id_note),
                 NoteId::ReceivedNoteId(id_note) => wallet::get_received_memo(&db, id_note),
@@ -166,7 +389,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
     ) -> Result<Option<CommitmentTree<Node>>, Self::Error> {
         let db = self.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             wallet::get_commitment_tree(&db, block_height)
         })
         .await
@@ -179,7 +402,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
     ) -> Result<Vec<(Self::NoteRef, IncrementalWitness<Node>)>, Self::Error> {
         let db = self.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             wallet::get_witnesses(&db, block_height)
         })
         .await
@@ -188,7 +411,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
     async fn get_nullifiers(&self) -> Result<Vec<(AccountId, Nullifier)>, Self::Error> {
         let db = self.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             wallet::get_nullifiers(&db)
         })
         .await
@@ -201,7 +424,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
     ) -> Result<Vec<SpendableNote>, Self::Error> {
         let db = self.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             wallet::transact::get_spendable_notes(&db, account, anchor_height)
         })
         .await
@@ -215,7 +438,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for WalletDbAs
     ) -> Result<Vec<SpendableNote>, Self::Error> {
         let db = self.clone();
         async_blocking(move || {
-            let db = db.inner.lock().unwrap();
+            let db = db.checkout_reader()?;
             wallet::transact::select_spendable_notes(&db, account, target_value, anchor_height)
         })
         .await
@@ -228,7 +451,7 @@ pub struct DataConnStmtCacheAsync<P> {
 }
 
 #[async_trait::async_trait]
-impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for DataConnStmtCacheAsync<P> {
+impl<P: consensus::Parameters + Clone + Send + Sync + 'static> WalletRead for DataConnStmtCacheAsync<P> {
     type Error = SqliteClientError;
     type NoteRef = NoteId;
     type TxRef = i64;
@@ -324,7 +547,7 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletRead for DataConnSt
 }
 
 #[async_trait::async_trait]
-impl<P: consensus::Parameters + Send + Sync + 'static> WalletWrite for DataConnStmtCacheAsync<P> {
+impl<P: consensus::Parameters + Clone + Send + Sync + 'static> WalletWrite for DataConnStmtCacheAsync<P> {
     #[allow(clippy::type_complexity)]
     async fn advance_by_block(
         &mut self,
@@ -373,3 +596,75 @@ impl<P: consensus::Parameters + Send + Sync + 'static> WalletWrite for DataConnS
         .await
     }
 }
+
+#[async_trait::async_trait]
+impl<P: consensus::Parameters + Clone + Send + Sync + 'static> zcash_extras::wallet::ShieldingWalletWrite
+    for DataConnStmtCacheAsync<P>
+{
+    async fn store_sent_tx_spending_utxos(
+        &mut self,
+        sent_tx: &SentTransaction,
+        utxos_spent: &[zcash_primitives::transaction::components::OutPoint],
+    ) -> Result<Self::TxRef, Self::Error> {
+        // Marking the swept UTXOs spent happens inside the same `transactionally` block as
+        // the rest of `store_sent_tx`'s writes, so a crash partway through can never leave
+        // one looking unspent (and therefore selectable by a later autoshielding sweep).
+        block_in_place(|| {
+            let db = self.wallet_db.inner.lock().unwrap();
+            let mut update_ops = db.get_update_ops()?;
+            update_ops.store_sent_tx_spending_utxos(sent_tx, utxos_spent)
+        })
+    }
+}
+
+impl<P: consensus::Parameters + Clone + Send + Sync + 'static> DataConnStmtCacheAsync<P> {
+    /// Async equivalent of [`crate::DataConnStmtCache::advance_by_blocks`]: applies an
+    /// entire batch of blocks in one transaction rather than one per block, so callers
+    /// catching up a long run of cached blocks aren't dominated by per-block commit
+    /// overhead.
+    pub async fn advance_by_blocks(
+        &mut self,
+        blocks: &[PrunedBlock],
+        updated_witnesses: &[(NoteId, IncrementalWitness<Node>)],
+    ) -> Result<Vec<(NoteId, IncrementalWitness<Node>)>, SqliteClientError> {
+        block_in_place(|| {
+            let db = self.wallet_db.inner.lock().unwrap();
+            let mut update_ops = db.get_update_ops()?;
+            update_ops.advance_by_blocks(blocks, updated_witnesses)
+        })
+    }
+
+    /// Records a transparent output paying one of our tracked addresses, at `height` if
+    /// given. [`WalletWrite::store_received_tx`] already records the transparent outputs
+    /// of the full transaction it is given; this records one learned some other way, or
+    /// sets the height of one already recorded.
+    pub async fn put_received_transparent_utxo(
+        &mut self,
+        address: zcash_primitives::legacy::TransparentAddress,
+        outpoint: zcash_primitives::transaction::components::OutPoint,
+        txout: zcash_primitives::transaction::components::TxOut,
+        height: Option<BlockHeight>,
+    ) -> Result<(), SqliteClientError> {
+        let db = self.wallet_db.clone();
+        async_blocking(move || {
+            let db = db.inner.lock().unwrap();
+            wallet::transparent::put_received_transparent_utxo(&db, &address, &outpoint, &txout, height)
+        })
+        .await
+    }
+
+    /// Marks the transparent output at `outpoint` as spent by `tx_ref`, so it is
+    /// excluded from future selection.
+    pub async fn mark_transparent_utxo_spent(
+        &mut self,
+        tx_ref: i64,
+        outpoint: zcash_primitives::transaction::components::OutPoint,
+    ) -> Result<(), SqliteClientError> {
+        let db = self.wallet_db.clone();
+        async_blocking(move || {
+            let db = db.inner.lock().unwrap();
+            wallet::transparent::mark_transparent_utxo_spent(&db, tx_ref, &outpoint)
+        })
+        .await
+    }
+}
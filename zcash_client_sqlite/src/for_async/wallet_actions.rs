@@ -0,0 +1,162 @@
+//! Convenience wrappers that bind the generic async wallet actions in
+//! [`zcash_extras::wallet`] to [`DataConnStmtCacheAsync`].
+
+use zcash_client_backend::{
+    address::RecipientAddress,
+    data_api::error::Error as DataApiError,
+    wallet::{AccountId, OvkPolicy},
+};
+use zcash_primitives::{
+    consensus::{self, BlockHeight},
+    memo::MemoBytes,
+    sapling::prover::TxProver,
+    transaction::components::Amount,
+    zip32::ExtendedSpendingKey,
+};
+use zcash_extras::wallet::{
+    create_spend_to_address, create_spend_to_recipients, decrypt_and_store_transaction,
+    shield_transparent_funds, ChangeStrategy, FeeRule, Payment,
+};
+
+use crate::error::SqliteClientError;
+use crate::for_async::DataConnStmtCacheAsync;
+
+/// Scans `tx` for outputs the wallet can decrypt and records any that are found.
+pub async fn decrypt_and_store_transaction_async<P>(
+    params: &P,
+    data: &mut DataConnStmtCacheAsync<P>,
+    tx: &zcash_primitives::transaction::Transaction,
+) -> Result<(), SqliteClientError>
+where
+    P: consensus::Parameters + Send + Sync + 'static,
+{
+    decrypt_and_store_transaction(params, data, tx).await
+}
+
+/// Builds and records a transaction paying `to` from `account`, using the prepared
+/// statement cache `data` for all database access.
+#[allow(clippy::too_many_arguments)]
+pub async fn create_spend_to_address_async<P, FR, CS>(
+    data: &mut DataConnStmtCacheAsync<P>,
+    params: &P,
+    prover: impl TxProver,
+    account: AccountId,
+    extsk: &ExtendedSpendingKey,
+    to: &RecipientAddress,
+    value: Amount,
+    memo: Option<MemoBytes>,
+    ovk_policy: OvkPolicy,
+    fee_rule: &FR,
+    change_strategy: &CS,
+) -> Result<i64, SqliteClientError>
+where
+    P: consensus::Parameters + Clone + Send + Sync + 'static,
+    FR: FeeRule,
+    CS: ChangeStrategy,
+{
+    create_spend_to_address(
+        data,
+        params,
+        prover,
+        account,
+        extsk,
+        to,
+        value,
+        memo,
+        ovk_policy,
+        fee_rule,
+        change_strategy,
+    )
+    .await
+}
+
+/// Builds and records a single transaction paying every recipient in `payments` from
+/// `account`, using the prepared statement cache `data` for all database access.
+#[allow(clippy::too_many_arguments)]
+pub async fn create_spend_to_recipients_async<P, FR, CS>(
+    data: &mut DataConnStmtCacheAsync<P>,
+    params: &P,
+    prover: impl TxProver,
+    account: AccountId,
+    extsk: &ExtendedSpendingKey,
+    payments: &[Payment],
+    ovk_policy: OvkPolicy,
+    fee_rule: &FR,
+    change_strategy: &CS,
+) -> Result<i64, SqliteClientError>
+where
+    P: consensus::Parameters + Clone + Send + Sync + 'static,
+    FR: FeeRule,
+    CS: ChangeStrategy,
+{
+    create_spend_to_recipients(
+        data,
+        params,
+        prover,
+        account,
+        extsk,
+        payments,
+        ovk_policy,
+        fee_rule,
+        change_strategy,
+    )
+    .await
+}
+
+/// Sweeps every unspent transparent output tracked for `account`'s transparent address
+/// as of `anchor_height` into a single shielded output, using the prepared statement
+/// cache `data` for the write path.
+#[allow(clippy::too_many_arguments)]
+pub async fn shield_transparent_funds_async<P, FR>(
+    data: &mut DataConnStmtCacheAsync<P>,
+    params: &P,
+    prover: impl TxProver,
+    account: AccountId,
+    extsk: &ExtendedSpendingKey,
+    transparent_sk: &secp256k1::SecretKey,
+    anchor_height: BlockHeight,
+    memo: Option<MemoBytes>,
+    fee_rule: &FR,
+) -> Result<i64, SqliteClientError>
+where
+    P: consensus::Parameters + Clone + Send + Sync + 'static,
+    FR: FeeRule,
+{
+    let address = data
+        .wallet_db
+        .get_transparent_address(account)
+        .await?
+        .ok_or_else(|| {
+            SqliteClientError::CorruptedData(
+                "No transparent address found for account".to_owned(),
+            )
+        })?;
+
+    let utxos = data
+        .wallet_db
+        .get_unspent_transparent_outputs(address, anchor_height)
+        .await?
+        .into_iter()
+        .map(|utxo| (utxo.outpoint, utxo.txout))
+        .collect::<Vec<_>>();
+
+    // `shield_transparent_funds` marks every swept UTXO spent atomically with recording
+    // the sweep transaction itself, via `ShieldingWalletWrite`, so there's no window where
+    // a crash could leave one looking unspent and selectable by a later call here.
+    shield_transparent_funds(
+        data,
+        params,
+        prover,
+        account,
+        extsk,
+        transparent_sk,
+        &utxos,
+        memo,
+        fee_rule,
+    )
+    .await
+}
+
+pub(crate) fn map_data_api_error(e: DataApiError<u32>) -> SqliteClientError {
+    SqliteClientError::from(e)
+}
@@ -23,6 +23,14 @@
 //! The `mainnet` feature configures the light client for use with the Zcash mainnet. By
 //! default, the light client is configured for use with the Zcash testnet.
 //!
+//! [`WalletDb::for_path_encrypted`] and [`WalletDb::rekey`] require `rusqlite`'s
+//! `bundled-sqlcipher` (or `sqlcipher`, to link against a system SQLCipher instead of
+//! building one) feature to be enabled in the workspace's `Cargo.toml`. Without it, `rusqlite`
+//! links plain SQLite, and `PRAGMA key`/`PRAGMA rekey` silently compile and run as no-ops
+//! against an unencrypted database rather than failing -- there is no way to detect this
+//! misconfiguration at compile time, so any deployment that calls `for_path_encrypted` should
+//! also run the `encrypted_*`/`rekey_*` tests in this crate to confirm the feature took effect.
+//!
 //! [`WalletRead`]: zcash_client_backend::data_api::WalletRead
 //! [`WalletWrite`]: zcash_client_backend::data_api::WalletWrite
 //! [`BlockSource`]: zcash_client_backend::data_api::BlockSource
@@ -37,6 +45,7 @@ extern crate core;
 use std::collections::HashMap;
 use std::path::Path;
 
+use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{Connection, Statement};
 
 use zcash_primitives::{
@@ -66,10 +75,57 @@ pub mod error;
 pub mod for_async;
 pub mod wallet;
 
+/// Either of the two kinds of SQLite connection a [`WalletDb`] may hold: the dedicated
+/// writer connection opened once at startup, or a connection checked out of a
+/// [`r2d2::Pool`] of read-only connections for the lifetime of a single read. Both
+/// deref to [`Connection`], so callers never need to distinguish between them.
+pub(crate) enum ConnHandle {
+    Owned(Connection),
+    Pooled(r2d2::PooledConnection<SqliteConnectionManager>),
+}
+
+impl std::ops::Deref for ConnHandle {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            ConnHandle::Owned(conn) => conn,
+            ConnHandle::Pooled(conn) => conn,
+        }
+    }
+}
+
+/// Issues the `PRAGMA key`/`PRAGMA cipher_*` statements that SQLCipher requires to be the
+/// very first statements executed against a freshly-opened connection to an encrypted
+/// database file.
+pub(crate) fn set_encryption_key(conn: &Connection, key: &str) -> Result<(), rusqlite::Error> {
+    conn.pragma_update(None, "key", &key.to_string())?;
+    conn.pragma_update(None, "cipher_page_size", &4096)?;
+    Ok(())
+}
+
+/// Confirms that the key just set via [`set_encryption_key`] was correct, by probing a
+/// table read: SQLCipher does not reject a wrong passphrase at `PRAGMA key` time, only
+/// once it actually tries to decrypt a page.
+pub(crate) fn verify_encryption_key(conn: &Connection) -> Result<(), SqliteClientError> {
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map_err(|_| SqliteClientError::CorruptedData("Incorrect database encryption key".to_string()))?;
+    Ok(())
+}
+
+/// The default value of [`WalletDb::max_reorg_depth`], chosen to match the shielded
+/// pool's historical reorg behavior on Zcash mainnet. Deployments with a different
+/// reorg safety margin (regtest, sidechains, high-reorg environments) should override it
+/// with [`WalletDb::set_max_reorg_depth`].
+pub const DEFAULT_MAX_REORG_DEPTH: u32 = 100;
+
 /// A wrapper for the SQLite connection to the wallet database.
 pub struct WalletDb<P> {
-    conn: Connection,
-    params: P,
+    pub(crate) conn: ConnHandle,
+    pub(crate) params: P,
+    pub(crate) max_reorg_depth: u32,
 }
 
 impl<P: consensus::Parameters> WalletDb<P> {
@@ -77,9 +133,67 @@ impl<P: consensus::Parameters> WalletDb<P> {
         &self.conn
     }
 
+    /// Returns the number of blocks of history this database retains enough witness data
+    /// to roll back through, via [`WalletWrite::advance_by_block`]'s witness pruning and
+    /// [`wallet::rewind_to_height`]'s bounds checking.
+    pub fn max_reorg_depth(&self) -> u32 {
+        self.max_reorg_depth
+    }
+
+    /// Overrides the number of blocks of history this database retains enough witness
+    /// data to roll back through. Must be set before the first block past the previous
+    /// horizon is scanned; shrinking it does not retroactively prune witnesses that are
+    /// now outside the new, smaller horizon.
+    pub fn set_max_reorg_depth(&mut self, max_reorg_depth: u32) {
+        self.max_reorg_depth = max_reorg_depth;
+    }
+
     /// Construct a connection to the wallet database stored at the specified path.
+    ///
+    /// This opens the database in WAL journal mode, so that the read-only connection
+    /// pool used by [`for_async::WalletDbAsync`] can run concurrently with this writer
+    /// connection instead of serializing behind it.
     pub fn for_path<F: AsRef<Path>>(path: F, params: P) -> Result<Self, rusqlite::Error> {
-        Connection::open(path).map(move |conn| WalletDb { conn, params })
+        Connection::open(path).map(move |conn| {
+            // Errors setting these pragmas are non-fatal: older SQLite builds without
+            // WAL support simply keep using the default rollback journal.
+            let _ = conn.pragma_update(None, "journal_mode", &"WAL");
+            let _ = conn.pragma_update(None, "synchronous", &"NORMAL");
+            WalletDb {
+                conn: ConnHandle::Owned(conn),
+                params,
+                max_reorg_depth: DEFAULT_MAX_REORG_DEPTH,
+            }
+        })
+    }
+
+    /// Construct a connection to the wallet database stored at the specified path,
+    /// encrypted at rest via SQLCipher with the given passphrase.
+    pub fn for_path_encrypted<F: AsRef<Path>>(
+        path: F,
+        key: &str,
+        params: P,
+    ) -> Result<Self, SqliteClientError> {
+        let conn = Connection::open(path)?;
+        set_encryption_key(&conn, key)?;
+        verify_encryption_key(&conn)?;
+
+        let _ = conn.pragma_update(None, "journal_mode", &"WAL");
+        let _ = conn.pragma_update(None, "synchronous", &"NORMAL");
+
+        Ok(WalletDb {
+            conn: ConnHandle::Owned(conn),
+            params,
+            max_reorg_depth: DEFAULT_MAX_REORG_DEPTH,
+        })
+    }
+
+    /// Rotates the passphrase protecting this database. `PRAGMA rekey` re-encrypts every
+    /// page in place within a single transaction, so a failure partway through leaves the
+    /// database readable under the old key rather than in a mixed state.
+    pub fn rekey(&self, new_key: &str) -> Result<(), SqliteClientError> {
+        self.conn.pragma_update(None, "rekey", &new_key.to_string())?;
+        Ok(())
     }
 
     /// Given a wallet database connection, obtain a handle for the write operations
@@ -94,23 +208,26 @@ impl<P: consensus::Parameters> WalletDb<P> {
                     VALUES (?, ?, ?, ?)",
                 )?,
                 stmt_insert_tx_meta: self.conn.prepare(
-                    "INSERT INTO transactions (txid, block, tx_index)
-                    VALUES (?, ?, ?)",
+                    "INSERT INTO transactions (txid, block, tx_index, account)
+                    VALUES (?, ?, ?, ?)",
                 )?,
                 stmt_update_tx_meta: self.conn.prepare(
                     "UPDATE transactions
-                    SET block = ?, tx_index = ? WHERE txid = ?",
+                    SET block = ?, tx_index = ? WHERE txid = ? AND account IS ?",
                 )?,
                 stmt_insert_tx_data: self.conn.prepare(
-                    "INSERT INTO transactions (txid, created, expiry_height, raw)
-                    VALUES (?, ?, ?, ?)",
+                    "INSERT INTO transactions (txid, created, expiry_height, raw, account)
+                    VALUES (?, ?, ?, ?, ?)",
                 )?,
                 stmt_update_tx_data: self.conn.prepare(
                     "UPDATE transactions
-                    SET expiry_height = ?, raw = ? WHERE txid = ?",
+                    SET expiry_height = ?, raw = ? WHERE txid = ? AND account IS ?",
                 )?,
+                // `transactions` rows are now keyed on `(txid, account)`, not `txid` alone,
+                // since one mined transaction can touch more than one of the wallet's own
+                // accounts and each gets its own row -- see `put_tx_meta`/`put_tx_data`.
                 stmt_select_tx_ref: self.conn.prepare(
-                    "SELECT id_tx FROM transactions WHERE txid = ?",
+                    "SELECT id_tx FROM transactions WHERE txid = ? AND account IS ?",
                 )?,
                 stmt_mark_recived_note_spent: self.conn.prepare(
                     "UPDATE received_notes SET spent = ? WHERE nf = ?"
@@ -255,28 +372,28 @@ impl<P: consensus::Parameters> WalletRead for WalletDb<P> {
 ///
 /// [`WalletWrite`]: zcash_client_backend::data_api::WalletWrite
 pub struct DataConnStmtCache<'a, P> {
-    wallet_db: &'a WalletDb<P>,
-    stmt_insert_block: Statement<'a>,
+    pub(crate) wallet_db: &'a WalletDb<P>,
+    pub(crate) stmt_insert_block: Statement<'a>,
 
-    stmt_insert_tx_meta: Statement<'a>,
-    stmt_update_tx_meta: Statement<'a>,
+    pub(crate) stmt_insert_tx_meta: Statement<'a>,
+    pub(crate) stmt_update_tx_meta: Statement<'a>,
 
-    stmt_insert_tx_data: Statement<'a>,
-    stmt_update_tx_data: Statement<'a>,
-    stmt_select_tx_ref: Statement<'a>,
+    pub(crate) stmt_insert_tx_data: Statement<'a>,
+    pub(crate) stmt_update_tx_data: Statement<'a>,
+    pub(crate) stmt_select_tx_ref: Statement<'a>,
 
-    stmt_mark_recived_note_spent: Statement<'a>,
+    pub(crate) stmt_mark_recived_note_spent: Statement<'a>,
 
-    stmt_insert_received_note: Statement<'a>,
-    stmt_update_received_note: Statement<'a>,
-    stmt_select_received_note: Statement<'a>,
+    pub(crate) stmt_insert_received_note: Statement<'a>,
+    pub(crate) stmt_update_received_note: Statement<'a>,
+    pub(crate) stmt_select_received_note: Statement<'a>,
 
-    stmt_insert_sent_note: Statement<'a>,
-    stmt_update_sent_note: Statement<'a>,
+    pub(crate) stmt_insert_sent_note: Statement<'a>,
+    pub(crate) stmt_update_sent_note: Statement<'a>,
 
-    stmt_insert_witness: Statement<'a>,
-    stmt_prune_witnesses: Statement<'a>,
-    stmt_update_expired: Statement<'a>,
+    pub(crate) stmt_insert_witness: Statement<'a>,
+    pub(crate) stmt_prune_witnesses: Statement<'a>,
+    pub(crate) stmt_update_expired: Statement<'a>,
 }
 
 impl<'a, P: consensus::Parameters> WalletRead for DataConnStmtCache<'a, P> {
@@ -392,62 +509,151 @@ impl<'a, P: consensus::Parameters> DataConnStmtCache<'a, P> {
     }
 }
 
-impl<'a, P: consensus::Parameters> WalletWrite for DataConnStmtCache<'a, P> {
+impl<'a, P: consensus::Parameters> DataConnStmtCache<'a, P> {
+    /// Inserts `block` and its transactions, recording witnesses for every newly- and
+    /// previously-received note. Unlike [`WalletWrite::advance_by_block`], this does
+    /// *not* prune stale witnesses or expire un-mined transactions -- callers batching
+    /// several blocks into one transaction via [`Self::advance_by_blocks`] defer both to
+    /// the end of the batch, since they only need to run once against the final height.
+    ///
+    /// Note: `block.transactions` is sourced from a `CompactBlock`, which (per the wire
+    /// protocol) carries only Sapling spend/output data to keep block download bandwidth
+    /// down -- it has no transparent transaction data at all. So, unlike
+    /// `store_received_tx`/`store_sent_tx`, this scan path cannot recognize transparent
+    /// receipts; a wallet that needs those from chain scanning (rather than from its own
+    /// sends, or from `decrypt_and_store_transaction` against a full transaction fetched
+    /// some other way) needs a separate path that fetches full transactions.
     #[allow(clippy::type_complexity)]
-    fn advance_by_block(
+    fn advance_by_block_inner(
         &mut self,
         block: &PrunedBlock,
-        updated_witnesses: &[(Self::NoteRef, IncrementalWitness<Node>)],
-    ) -> Result<Vec<(Self::NoteRef, IncrementalWitness<Node>)>, Self::Error> {
-        // database updates for each block are transactional
-        self.transactionally(|up| {
-            // Insert the block into the database.
-            wallet::insert_block(
-                up,
-                block.block_height,
-                block.block_hash,
-                block.block_time,
-                &block.commitment_tree,
-            )?;
-
-            let mut new_witnesses = vec![];
-            for tx in block.transactions {
-                let tx_row = wallet::put_tx_meta(up, &tx, block.block_height)?;
-
-                // Mark notes as spent and remove them from the scanning cache
-                for spend in &tx.shielded_spends {
-                    wallet::mark_spent(up, tx_row, &spend.nf)?;
+        updated_witnesses: &[(NoteId, IncrementalWitness<Node>)],
+    ) -> Result<Vec<(NoteId, IncrementalWitness<Node>)>, SqliteClientError> {
+        // Insert the block into the database.
+        wallet::insert_block(
+            self,
+            block.block_height,
+            block.block_hash,
+            block.block_time,
+            &block.commitment_tree,
+        )?;
+
+        let mut new_witnesses = vec![];
+        for tx in block.transactions {
+            // A single mined transaction can touch more than one of the wallet's own
+            // accounts (e.g. an internal transfer), and `transactions` rows are now keyed
+            // on `(height, tx_index, account)`, so each account gets its own row rather
+            // than sharing one.
+            let mut tx_refs: HashMap<AccountId, i64> = HashMap::new();
+
+            // Mark notes as spent and remove them from the scanning cache, recording each
+            // spend against the row for the account that owned the note.
+            for spend in &tx.shielded_spends {
+                if let Some(account) = wallet::get_account_for_nullifier(self, &spend.nf)? {
+                    let tx_row = match tx_refs.get(&account) {
+                        Some(tx_row) => *tx_row,
+                        None => {
+                            let tx_row = wallet::put_tx_meta(self, &tx, block.block_height, account)?;
+                            tx_refs.insert(account, tx_row);
+                            tx_row
+                        }
+                    };
+                    wallet::mark_spent(self, tx_row, &spend.nf)?;
                 }
+            }
 
-                for output in &tx.shielded_outputs {
-                    let received_note_id = wallet::put_received_note(up, output, tx_row)?;
+            for output in &tx.shielded_outputs {
+                let account = output.account();
+                let tx_row = match tx_refs.get(&account) {
+                    Some(tx_row) => *tx_row,
+                    None => {
+                        let tx_row = wallet::put_tx_meta(self, &tx, block.block_height, account)?;
+                        tx_refs.insert(account, tx_row);
+                        tx_row
+                    }
+                };
+
+                let received_note_id = wallet::put_received_note(self, output, tx_row)?;
+
+                // Save witness for note.
+                new_witnesses.push((received_note_id, output.witness.clone()));
+            }
+        }
 
-                    // Save witness for note.
-                    new_witnesses.push((received_note_id, output.witness.clone()));
-                }
+        // Insert current new_witnesses into the database.
+        for (received_note_id, witness) in updated_witnesses.iter().chain(new_witnesses.iter()) {
+            if let NoteId::ReceivedNoteId(rnid) = *received_note_id {
+                wallet::insert_witness(self, rnid, witness, block.block_height)?;
+            } else {
+                return Err(SqliteClientError::InvalidNoteId);
             }
+        }
 
-            // Insert current new_witnesses into the database.
-            for (received_note_id, witness) in updated_witnesses.iter().chain(new_witnesses.iter())
-            {
-                if let NoteId::ReceivedNoteId(rnid) = *received_note_id {
-                    wallet::insert_witness(up, rnid, witness, block.block_height)?;
-                } else {
-                    return Err(SqliteClientError::InvalidNoteId);
-                }
+        Ok(new_witnesses)
+    }
+
+    /// Prunes witnesses older than `max_reorg_depth` blocks below `as_of_height`, and
+    /// expires un-mined transactions whose expiry height has passed as of it. Shared by
+    /// [`WalletWrite::advance_by_block`] (run once per block) and
+    /// [`Self::advance_by_blocks`] (run once per batch).
+    fn prune_and_expire(&mut self, as_of_height: BlockHeight) -> Result<(), SqliteClientError> {
+        let max_reorg_depth = self.wallet_db.max_reorg_depth;
+        let below_height = if as_of_height < BlockHeight::from(max_reorg_depth) {
+            BlockHeight::from(0)
+        } else {
+            as_of_height - max_reorg_depth
+        };
+        wallet::prune_witnesses(self, below_height)?;
+        wallet::update_expired_notes(self, as_of_height)?;
+
+        Ok(())
+    }
+
+    /// Batched equivalent of [`WalletWrite::advance_by_block`]: applies every block in
+    /// `blocks` inside a single transaction instead of one `BEGIN IMMEDIATE`/`COMMIT`
+    /// pair per block, and defers witness pruning and expired-note updates to after the
+    /// last block rather than repeating both for every block in between. This is the
+    /// difference that matters when catching up thousands of blocks from the cache
+    /// database: per-block transaction overhead otherwise dominates scan time.
+    ///
+    /// A crash partway through the batch rolls the whole transaction back, so the
+    /// database is left at whatever block boundary it last successfully committed to --
+    /// never partway through this batch.
+    #[allow(clippy::type_complexity)]
+    pub fn advance_by_blocks(
+        &mut self,
+        blocks: &[PrunedBlock],
+        updated_witnesses: &[(NoteId, IncrementalWitness<Node>)],
+    ) -> Result<Vec<(NoteId, IncrementalWitness<Node>)>, SqliteClientError> {
+        let last_height = match blocks.last() {
+            Some(block) => block.block_height,
+            None => return Ok(updated_witnesses.to_vec()),
+        };
+
+        self.transactionally(|up| {
+            let mut witnesses = updated_witnesses.to_vec();
+            for block in blocks {
+                witnesses = up.advance_by_block_inner(block, &witnesses)?;
             }
 
-            // Prune the stored witnesses (we only expect rollbacks of at most 100 blocks).
-            let below_height = if block.block_height < BlockHeight::from(100) {
-                BlockHeight::from(0)
-            } else {
-                block.block_height - 100
-            };
-            wallet::prune_witnesses(up, below_height)?;
+            up.prune_and_expire(last_height)?;
 
-            // Update now-expired transactions that didn't get mined.
-            wallet::update_expired_notes(up, block.block_height)?;
+            Ok(witnesses)
+        })
+    }
+}
 
+impl<'a, P: consensus::Parameters> WalletWrite for DataConnStmtCache<'a, P> {
+    #[allow(clippy::type_complexity)]
+    fn advance_by_block(
+        &mut self,
+        block: &PrunedBlock,
+        updated_witnesses: &[(Self::NoteRef, IncrementalWitness<Node>)],
+    ) -> Result<Vec<(Self::NoteRef, IncrementalWitness<Node>)>, Self::Error> {
+        // database updates for each block are transactional
+        self.transactionally(|up| {
+            let new_witnesses = up.advance_by_block_inner(block, updated_witnesses)?;
+            up.prune_and_expire(block.block_height)?;
             Ok(new_witnesses)
         })
     }
@@ -457,50 +663,44 @@ impl<'a, P: consensus::Parameters> WalletWrite for DataConnStmtCache<'a, P> {
         received_tx: &ReceivedTransaction,
     ) -> Result<Self::TxRef, Self::Error> {
         self.transactionally(|up| {
-            let tx_ref = wallet::put_tx_data(up, received_tx.tx, None)?;
+            // As in `advance_by_block_inner`, each account touched by this transaction's
+            // outputs gets its own `transactions` row.
+            let mut tx_refs: HashMap<AccountId, i64> = HashMap::new();
+            let mut last_tx_ref = None;
 
             for output in received_tx.outputs {
+                let account = output.account();
+                let tx_ref = match tx_refs.get(&account) {
+                    Some(tx_ref) => *tx_ref,
+                    None => {
+                        let tx_ref = wallet::put_tx_data(up, received_tx.tx, None, account)?;
+                        tx_refs.insert(account, tx_ref);
+                        tx_ref
+                    }
+                };
+
                 if output.outgoing {
                     wallet::put_sent_note(up, output, tx_ref)?;
                 } else {
                     wallet::put_received_note(up, output, tx_ref)?;
                 }
+                last_tx_ref = Some(tx_ref);
             }
 
-            Ok(tx_ref)
+            // `received_tx.outputs` only carries shielded outputs, so transparent receipts
+            // (including our own change) are recognized separately here, against the full
+            // transaction's `vout`.
+            wallet::record_transparent_outputs(up, received_tx.tx)?;
+
+            // `decrypt_and_store_transaction` only calls `store_received_tx` once it has
+            // confirmed `outputs` is non-empty.
+            Ok(last_tx_ref.expect("store_received_tx requires at least one output"))
         })
     }
 
     fn store_sent_tx(&mut self, sent_tx: &SentTransaction) -> Result<Self::TxRef, Self::Error> {
         // Update the database atomically, to ensure the result is internally consistent.
-        self.transactionally(|up| {
-            let tx_ref = wallet::put_tx_data(up, &sent_tx.tx, Some(sent_tx.created))?;
-
-            // Mark notes as spent.
-            //
-            // This locks the notes so they aren't selected again by a subsequent call to
-            // create_spend_to_address() before this transaction has been mined (at which point the notes
-            // get re-marked as spent).
-            //
-            // Assumes that create_spend_to_address() will never be called in parallel, which is a
-            // reasonable assumption for a light client such as a mobile phone.
-            for spend in &sent_tx.tx.shielded_spends {
-                wallet::mark_spent(up, tx_ref, &spend.nullifier)?;
-            }
-
-            wallet::insert_sent_note(
-                up,
-                tx_ref,
-                sent_tx.output_index,
-                sent_tx.account,
-                sent_tx.recipient_address,
-                sent_tx.value,
-                sent_tx.memo.as_ref(),
-            )?;
-
-            // Return the row number of the transaction, so the caller can fetch it for sending.
-            Ok(tx_ref)
-        })
+        self.transactionally(|up| Self::store_sent_tx_inner(up, sent_tx, &[]))
     }
 
     fn rewind_to_height(&mut self, block_height: BlockHeight) -> Result<(), Self::Error> {
@@ -508,14 +708,90 @@ impl<'a, P: consensus::Parameters> WalletWrite for DataConnStmtCache<'a, P> {
     }
 }
 
+impl<'a, P: consensus::Parameters> DataConnStmtCache<'a, P> {
+    /// Shared body of [`WalletWrite::store_sent_tx`] and
+    /// [`Self::store_sent_tx_spending_utxos`]: records `sent_tx`, then marks every outpoint
+    /// in `utxos_spent` as spent by the resulting transaction in the same write. Callers
+    /// that did not fund `sent_tx` from transparent UTXOs pass an empty slice.
+    fn store_sent_tx_inner(
+        up: &mut Self,
+        sent_tx: &SentTransaction,
+        utxos_spent: &[zcash_primitives::transaction::components::OutPoint],
+    ) -> Result<i64, SqliteClientError> {
+        let tx_ref = wallet::put_tx_data(up, &sent_tx.tx, Some(sent_tx.created), sent_tx.account)?;
+
+        // Mark notes as spent.
+        //
+        // This locks the notes so they aren't selected again by a subsequent call to
+        // create_spend_to_address() before this transaction has been mined (at which point the notes
+        // get re-marked as spent).
+        //
+        // Assumes that create_spend_to_address() will never be called in parallel, which is a
+        // reasonable assumption for a light client such as a mobile phone.
+        for spend in &sent_tx.tx.shielded_spends {
+            wallet::mark_spent(up, tx_ref, &spend.nullifier)?;
+        }
+
+        wallet::insert_sent_note(
+            up,
+            tx_ref,
+            sent_tx.output_index,
+            sent_tx.account,
+            sent_tx.recipient_address,
+            sent_tx.value,
+            sent_tx.memo.as_ref(),
+        )?;
+
+        // Recognize any transparent change or other self-payments in `sent_tx.tx`'s `vout`,
+        // same as `store_received_tx`.
+        wallet::record_transparent_outputs(up, &sent_tx.tx)?;
+
+        // Mark every transparent UTXO this transaction spent, in the same write as
+        // everything above, so a crash here can never leave a swept UTXO looking unspent.
+        for outpoint in utxos_spent {
+            wallet::transparent::mark_transparent_utxo_spent(up.wallet_db, tx_ref, outpoint)?;
+        }
+
+        // Return the row number of the transaction, so the caller can fetch it for sending.
+        Ok(tx_ref)
+    }
+
+    /// Like [`WalletWrite::store_sent_tx`], but also marks every outpoint in `utxos_spent`
+    /// as spent by the resulting transaction, atomically with the rest of the write -- the
+    /// sync counterpart backing [`for_async::DataConnStmtCacheAsync`]'s implementation of
+    /// [`zcash_extras::wallet::ShieldingWalletWrite`].
+    pub fn store_sent_tx_spending_utxos(
+        &mut self,
+        sent_tx: &SentTransaction,
+        utxos_spent: &[zcash_primitives::transaction::components::OutPoint],
+    ) -> Result<i64, SqliteClientError> {
+        self.transactionally(|up| Self::store_sent_tx_inner(up, sent_tx, utxos_spent))
+    }
+}
+
 /// A wrapper for the SQLite connection to the block cache database.
-pub struct BlockDb(Connection);
+pub struct BlockDb(pub(crate) Connection);
 
 impl BlockDb {
     /// Opens a connection to the wallet database stored at the specified path.
     pub fn for_path<P: AsRef<Path>>(path: P) -> Result<Self, rusqlite::Error> {
         Connection::open(path).map(BlockDb)
     }
+
+    /// Opens a connection to the cache database stored at the specified path, encrypted
+    /// at rest via SQLCipher with the given passphrase.
+    pub fn for_path_encrypted<P: AsRef<Path>>(path: P, key: &str) -> Result<Self, SqliteClientError> {
+        let conn = Connection::open(path)?;
+        set_encryption_key(&conn, key)?;
+        verify_encryption_key(&conn)?;
+        Ok(BlockDb(conn))
+    }
+
+    /// Rotates the passphrase protecting this database.
+    pub fn rekey(&self, new_key: &str) -> Result<(), SqliteClientError> {
+        self.0.pragma_update(None, "rekey", &new_key.to_string())?;
+        Ok(())
+    }
 }
 
 impl BlockSource for BlockDb {
@@ -534,7 +810,7 @@ impl BlockSource for BlockDb {
     }
 }
 
-fn address_from_extfvk<P: consensus::Parameters>(
+pub(crate) fn address_from_extfvk<P: consensus::Parameters>(
     params: &P,
     extfvk: &ExtendedFullViewingKey,
 ) -> String {
@@ -551,7 +827,9 @@ mod tests {
 
     use zcash_primitives::consensus::{BlockHeight, Network, NetworkUpgrade, Parameters};
 
-    use super::BlockDb;
+    use super::{BlockDb, WalletDb};
+    use crate::error::SqliteClientError;
+    use crate::wallet::{self, init::init_wallet_db};
 
     #[cfg(feature = "mainnet")]
     pub(crate) fn network() -> Network {
@@ -587,4 +865,160 @@ mod tests {
             .execute(params![u32::from(cb.height()), cb_bytes,])
             .unwrap();
     }
+
+    fn insert_fake_block(db_data: &WalletDb<Network>, height: BlockHeight) {
+        db_data
+            .conn
+            .execute(
+                "INSERT INTO blocks (height, hash, time, sapling_tree) VALUES (?, ?, ?, ?)",
+                params![u32::from(height), vec![0u8; 32], 0, vec![] as Vec<u8>],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn rewind_within_max_reorg_depth_succeeds() {
+        let mut db_data = WalletDb::for_path(":memory:", network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+        db_data.set_max_reorg_depth(10);
+
+        let last_scanned_height = sapling_activation_height() + 20;
+        insert_fake_block(&db_data, last_scanned_height);
+
+        assert!(wallet::rewind_to_height(&db_data, last_scanned_height - 5).is_ok());
+    }
+
+    #[test]
+    fn rewind_past_max_reorg_depth_errors() {
+        let mut db_data = WalletDb::for_path(":memory:", network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+        db_data.set_max_reorg_depth(10);
+
+        let last_scanned_height = sapling_activation_height() + 20;
+        insert_fake_block(&db_data, last_scanned_height);
+
+        // Rewinding past the configured `max_reorg_depth` is rejected, since the
+        // witness history needed to do so correctly has already been pruned.
+        match wallet::rewind_to_height(&db_data, last_scanned_height - 15) {
+            Err(SqliteClientError::CorruptedData(_)) => (),
+            other => panic!("Expected Err(CorruptedData(_)), got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rewind_past_actually_retained_witnesses_errors_even_after_raising_max_reorg_depth() {
+        let mut db_data = WalletDb::for_path(":memory:", network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+        db_data.set_max_reorg_depth(10);
+
+        let last_scanned_height = sapling_activation_height() + 20;
+        insert_fake_block(&db_data, last_scanned_height);
+
+        // Witnesses below `last_scanned_height - 10` get pruned while `max_reorg_depth`
+        // is still 10.
+        let pruned_to = last_scanned_height - 10;
+        wallet::prune_witnesses(&mut db_data.get_update_ops().unwrap(), pruned_to).unwrap();
+
+        // Raising `max_reorg_depth` afterwards must not let a rewind reach back past what
+        // was actually pruned -- that witness data is already gone.
+        db_data.set_max_reorg_depth(1000);
+
+        match wallet::rewind_to_height(&db_data, pruned_to - 1) {
+            Err(SqliteClientError::CorruptedData(_)) => (),
+            other => panic!("Expected Err(CorruptedData(_)), got {:?}", other),
+        }
+
+        // A rewind to exactly the retained horizon is still fine.
+        assert!(wallet::rewind_to_height(&db_data, pruned_to).is_ok());
+    }
+
+    #[test]
+    fn diversified_addresses_advance_and_never_repeat() {
+        use wallet::diversified::{get_current_diversified_address, get_next_diversified_address};
+        use wallet::init::init_accounts_table;
+        use zcash_client_backend::keys::spending_key;
+        use zcash_client_backend::wallet::AccountId;
+        use zcash_primitives::{constants::testnet::COIN_TYPE, zip32::ExtendedFullViewingKey};
+
+        let db_data = WalletDb::for_path(":memory:", network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let account = AccountId(0);
+        let extsk = spending_key(&[0; 32][..], COIN_TYPE, account.0);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk]).unwrap();
+
+        // Peeking the current address doesn't advance the stored offset.
+        let first = get_current_diversified_address(&db_data, account).unwrap();
+        assert_eq!(get_current_diversified_address(&db_data, account).unwrap(), first);
+
+        // Handing it out returns the same address peeking found...
+        assert_eq!(get_next_diversified_address(&db_data, account).unwrap(), first);
+
+        // ...and the next one handed out is different, matching what peeking now returns.
+        let peeked = get_current_diversified_address(&db_data, account).unwrap();
+        let second = get_next_diversified_address(&db_data, account).unwrap();
+        assert_ne!(second, first);
+        assert_eq!(second, peeked);
+    }
+
+    // These exercise `for_path_encrypted`/`rekey` against a real file (SQLCipher encrypts
+    // a file at rest, so there's nothing to observe against `:memory:`). They only prove
+    // anything when run against a `rusqlite` build linked with SQLCipher -- e.g. via its
+    // `bundled-sqlcipher` feature; against a vanilla SQLite build, `PRAGMA key` is a silent
+    // no-op and `verify_encryption_key`'s probe read would "succeed" against a plaintext
+    // database too.
+
+    #[test]
+    fn encrypted_round_trip_with_correct_key_succeeds() {
+        let data_file = tempfile::NamedTempFile::new().unwrap();
+
+        {
+            let db_data = WalletDb::for_path_encrypted(data_file.path(), "correct horse", network()).unwrap();
+            init_wallet_db(&db_data).unwrap();
+            insert_fake_block(&db_data, sapling_activation_height());
+        }
+
+        // Reopening with the same key can read back what was written.
+        let db_data = WalletDb::for_path_encrypted(data_file.path(), "correct horse", network()).unwrap();
+        assert_eq!(
+            wallet::block_height_extrema(&db_data).unwrap(),
+            Some((sapling_activation_height(), sapling_activation_height())),
+        );
+    }
+
+    #[test]
+    fn encrypted_open_with_wrong_key_fails() {
+        let data_file = tempfile::NamedTempFile::new().unwrap();
+
+        {
+            let db_data = WalletDb::for_path_encrypted(data_file.path(), "correct horse", network()).unwrap();
+            init_wallet_db(&db_data).unwrap();
+        }
+
+        match WalletDb::for_path_encrypted(data_file.path(), "wrong key", network()) {
+            Err(SqliteClientError::CorruptedData(_)) => (),
+            other => panic!("Expected Err(CorruptedData(_)), got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rekey_then_reopen_with_new_key_succeeds() {
+        let data_file = tempfile::NamedTempFile::new().unwrap();
+
+        {
+            let db_data = WalletDb::for_path_encrypted(data_file.path(), "old key", network()).unwrap();
+            init_wallet_db(&db_data).unwrap();
+            db_data.rekey("new key").unwrap();
+        }
+
+        // The old key no longer opens the database...
+        match WalletDb::for_path_encrypted(data_file.path(), "old key", network()) {
+            Err(SqliteClientError::CorruptedData(_)) => (),
+            other => panic!("Expected Err(CorruptedData(_)), got {:?}", other),
+        }
+
+        // ...but the new one does.
+        assert!(WalletDb::for_path_encrypted(data_file.path(), "new key", network()).is_ok());
+    }
 }
@@ -0,0 +1,845 @@
+//! Functions for querying information in the data database.
+//!
+//! These functions operate on a [`Connection`]-backed [`WalletDb`] (or a
+//! [`DataConnStmtCache`] wrapping prepared statements against one), and are the basis on
+//! which the [`WalletRead`]/[`WalletWrite`] trait implementations in the crate root are
+//! built.
+//!
+//! [`WalletRead`]: zcash_client_backend::data_api::WalletRead
+//! [`WalletWrite`]: zcash_client_backend::data_api::WalletWrite
+
+use rusqlite::{params, OptionalExtension};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use zcash_client_backend::wallet::AccountId;
+use zcash_client_backend::{
+    encoding::{decode_extended_full_viewing_key, decode_payment_address},
+    wallet::SpendableNote,
+};
+use zcash_extras::NoteId;
+use zcash_primitives::{
+    block::BlockHash,
+    consensus::{self, BlockHeight},
+    memo::Memo,
+    merkle_tree::{CommitmentTree, IncrementalWitness},
+    sapling::{Node, Note, Nullifier, PaymentAddress},
+    transaction::{components::Amount, TxId},
+    zip32::ExtendedFullViewingKey,
+};
+
+use crate::error::SqliteClientError;
+use crate::{DataConnStmtCache, WalletDb};
+
+pub mod diversified;
+pub mod init;
+pub mod transact;
+pub mod transparent;
+
+/// Returns the (min, max) block heights currently stored in the data database, or `None`
+/// if the database has not yet been populated with any block data.
+pub fn block_height_extrema<P>(
+    wdb: &WalletDb<P>,
+) -> Result<Option<(BlockHeight, BlockHeight)>, rusqlite::Error> {
+    wdb.conn
+        .query_row(
+            "SELECT MIN(height), MAX(height) FROM blocks",
+            [],
+            |row| {
+                let min_height: Option<u32> = row.get(0)?;
+                let max_height: Option<u32> = row.get(1)?;
+                Ok(min_height
+                    .zip(max_height)
+                    .map(|(min, max)| (BlockHeight::from(min), BlockHeight::from(max))))
+            },
+        )
+        .map(|ext| ext.filter(|_| true))
+}
+
+/// Looks up the block hash that was recorded as being mined at the given height.
+pub fn get_block_hash<P>(
+    wdb: &WalletDb<P>,
+    block_height: BlockHeight,
+) -> Result<Option<BlockHash>, rusqlite::Error> {
+    wdb.conn
+        .query_row(
+            "SELECT hash FROM blocks WHERE height = ?",
+            params![u32::from(block_height)],
+            |row| {
+                let row_data: Vec<u8> = row.get(0)?;
+                Ok(BlockHash::from_slice(&row_data))
+            },
+        )
+        .optional()
+}
+
+/// Returns the height at which the transaction with the given ID was mined, if known.
+pub fn get_tx_height<P>(
+    wdb: &WalletDb<P>,
+    txid: TxId,
+) -> Result<Option<BlockHeight>, rusqlite::Error> {
+    wdb.conn
+        .query_row(
+            "SELECT block FROM transactions WHERE txid = ?",
+            params![txid.as_ref().to_vec()],
+            |row| row.get(0).map(|h: Option<u32>| h.map(BlockHeight::from)),
+        )
+        .optional()
+        .map(|opt| opt.flatten())
+}
+
+/// Returns the extended full viewing key for every account known to the wallet.
+pub fn get_extended_full_viewing_keys<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+) -> Result<HashMap<AccountId, ExtendedFullViewingKey>, SqliteClientError> {
+    let mut stmt = wdb
+        .conn
+        .prepare("SELECT account, extfvk FROM accounts")?;
+    let rows = stmt.query_map([], |row| {
+        let account: u32 = row.get(0)?;
+        let extfvk: String = row.get(1)?;
+        Ok((account, extfvk))
+    })?;
+
+    let mut res = HashMap::new();
+    for row in rows {
+        let (account, extfvk_str) = row?;
+        let extfvk = decode_extended_full_viewing_key(
+            wdb.params.hrp_sapling_extended_full_viewing_key(),
+            &extfvk_str,
+        )
+        .map_err(|_| SqliteClientError::IncorrectHrpExtFvk)?
+        .ok_or(SqliteClientError::IncorrectHrpExtFvk)?;
+
+        res.insert(AccountId(account), extfvk);
+    }
+
+    Ok(res)
+}
+
+/// Returns the default shielded address for the given account, if the account is known
+/// to the wallet.
+pub fn get_address<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    account: AccountId,
+) -> Result<Option<PaymentAddress>, SqliteClientError> {
+    let addr: Option<String> = wdb
+        .conn
+        .query_row(
+            "SELECT address FROM accounts WHERE account = ?",
+            params![account.0],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    addr.map(|addr| {
+        decode_payment_address(wdb.params.hrp_sapling_payment_address(), &addr)
+            .map_err(|_| SqliteClientError::CorruptedData("Not a valid Sapling address".into()))?
+            .ok_or_else(|| SqliteClientError::CorruptedData("Not a valid Sapling address".into()))
+    })
+    .transpose()
+}
+
+/// Checks whether the given extended full viewing key matches the one stored for the
+/// given account.
+pub fn is_valid_account_extfvk<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    account: AccountId,
+    extfvk: &ExtendedFullViewingKey,
+) -> Result<bool, SqliteClientError> {
+    Ok(get_extended_full_viewing_keys(wdb)?
+        .get(&account)
+        .map(|k| k == extfvk)
+        .unwrap_or(false))
+}
+
+/// Returns the balance for the given account as of the given anchor height: the sum of
+/// its shielded notes and transparent outputs that have been confirmed and are not
+/// currently known to be spent.
+pub fn get_balance_at<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    account: AccountId,
+    anchor_height: BlockHeight,
+) -> Result<Amount, SqliteClientError> {
+    let shielded_balance = wdb.conn.query_row(
+        "SELECT SUM(value) FROM received_notes
+        INNER JOIN transactions ON transactions.id_tx = received_notes.tx
+        WHERE received_notes.account = ?
+        AND received_notes.spent IS NULL
+        AND transactions.block <= ?",
+        params![account.0, u32::from(anchor_height)],
+        |row| row.get(0).or(Ok(0)),
+    )?;
+
+    let shielded_balance = match Amount::from_i64(shielded_balance) {
+        Ok(amount) if !amount.is_negative() => amount,
+        _ => {
+            return Err(SqliteClientError::CorruptedData(
+                "Sum of values in received_notes is out of range".to_string(),
+            ))
+        }
+    };
+
+    // Autoshielding relies on transparent funds being visible through the same balance
+    // query shielded funds are, rather than requiring callers to sum the two separately.
+    let transparent_balance = transparent::get_transparent_balance(wdb, account, anchor_height)?;
+
+    Ok(shielded_balance + transparent_balance)
+}
+
+/// Returns the memo for a sent note.
+pub fn get_sent_memo<P>(wdb: &WalletDb<P>, id_note: i64) -> Result<Memo, SqliteClientError> {
+    let memo_bytes: Vec<u8> = wdb.conn.query_row(
+        "SELECT memo FROM sent_notes WHERE id_note = ?",
+        params![id_note],
+        |row| row.get(0),
+    )?;
+
+    Memo::from_bytes(&memo_bytes).map_err(|_| SqliteClientError::InvalidNote)
+}
+
+/// Returns the memo for a received note.
+pub fn get_received_memo<P>(wdb: &WalletDb<P>, id_note: i64) -> Result<Memo, SqliteClientError> {
+    let memo_bytes: Vec<u8> = wdb.conn.query_row(
+        "SELECT memo FROM received_notes WHERE id_note = ?",
+        params![id_note],
+        |row| row.get(0),
+    )?;
+
+    Memo::from_bytes(&memo_bytes).map_err(|_| SqliteClientError::InvalidNote)
+}
+
+/// Returns the commitment tree stored as of the given block height, if any.
+pub fn get_commitment_tree<P>(
+    wdb: &WalletDb<P>,
+    block_height: BlockHeight,
+) -> Result<Option<CommitmentTree<Node>>, SqliteClientError> {
+    wdb.conn
+        .query_row(
+            "SELECT sapling_tree FROM blocks WHERE height = ?",
+            params![u32::from(block_height)],
+            |row| {
+                let row_data: Vec<u8> = row.get(0)?;
+                Ok(CommitmentTree::read(&row_data[..]))
+            },
+        )
+        .optional()?
+        .transpose()
+        .map_err(|e| SqliteClientError::CorruptedData(e.to_string()))
+}
+
+/// Returns the stored witnesses for notes as of the given block height.
+#[allow(clippy::type_complexity)]
+pub fn get_witnesses<P>(
+    wdb: &WalletDb<P>,
+    block_height: BlockHeight,
+) -> Result<Vec<(NoteId, IncrementalWitness<Node>)>, SqliteClientError> {
+    let mut stmt = wdb
+        .conn
+        .prepare("SELECT note, witness FROM sapling_witnesses WHERE block = ?")?;
+    let witnesses = stmt
+        .query_map(params![u32::from(block_height)], |row| {
+            let id_note: i64 = row.get(0)?;
+            let wdata: Vec<u8> = row.get(1)?;
+            Ok((id_note, wdata))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    witnesses
+        .into_iter()
+        .map(|(id_note, wdata)| {
+            IncrementalWitness::read(&wdata[..])
+                .map(|witness| (NoteId::ReceivedNoteId(id_note), witness))
+                .map_err(|e| SqliteClientError::CorruptedData(e.to_string()))
+        })
+        .collect()
+}
+
+/// Returns every nullifier the wallet is watching for, across all accounts.
+pub fn get_nullifiers<P>(
+    wdb: &WalletDb<P>,
+) -> Result<Vec<(AccountId, Nullifier)>, SqliteClientError> {
+    let mut stmt = wdb
+        .conn
+        .prepare("SELECT account, nf FROM received_notes WHERE spent IS NULL AND nf IS NOT NULL")?;
+    let nullifiers = stmt.query_map([], |row| {
+        let account: u32 = row.get(0)?;
+        let nf_bytes: Vec<u8> = row.get(1)?;
+        Ok((AccountId(account), Nullifier::from_slice(&nf_bytes).unwrap()))
+    })?;
+
+    nullifiers
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(SqliteClientError::from)
+}
+
+/// Records a new block, including its derived commitment tree.
+pub fn insert_block<'a, P>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    block_height: BlockHeight,
+    block_hash: BlockHash,
+    block_time: u32,
+    commitment_tree: &CommitmentTree<Node>,
+) -> Result<(), SqliteClientError> {
+    let mut encoded_tree = Vec::new();
+    commitment_tree.write(&mut encoded_tree).unwrap();
+
+    stmts.stmt_insert_block.execute(params![
+        u32::from(block_height),
+        &block_hash.0[..],
+        block_time,
+        encoded_tree,
+    ])?;
+
+    Ok(())
+}
+
+/// Looks up (or inserts) the internal row ID for the given transaction, scoped to
+/// `account`. A single mined transaction can touch more than one of the wallet's own
+/// accounts (e.g. an internal transfer), and `transactions` rows are keyed on
+/// `(height, tx_index, account)`, so each account a transaction touches gets its own row;
+/// callers must invoke this once per distinct account rather than once per transaction.
+pub fn put_tx_meta<'a, P, N>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    tx: &N,
+    height: BlockHeight,
+    account: AccountId,
+) -> Result<i64, SqliteClientError>
+where
+    N: TxMeta,
+{
+    let tx_ref = if let Some(id_tx) = stmts
+        .stmt_select_tx_ref
+        .query_row(params![tx.txid().as_ref().to_vec(), account.0], |row| {
+            row.get(0)
+        })
+        .optional()?
+    {
+        stmts.stmt_update_tx_meta.execute(params![
+            u32::from(height),
+            tx.tx_index(),
+            tx.txid().as_ref().to_vec(),
+            account.0,
+        ])?;
+        id_tx
+    } else {
+        stmts.stmt_insert_tx_meta.execute(params![
+            tx.txid().as_ref().to_vec(),
+            u32::from(height),
+            tx.tx_index(),
+            account.0,
+        ])?;
+        stmts.wallet_db.conn.last_insert_rowid()
+    };
+
+    // Display timestamps default to the time the block that mined this transaction was
+    // seen; `set_tx_account_metadata` will not overwrite this once it is set.
+    let block_time: Option<i64> = stmts
+        .wallet_db
+        .conn
+        .query_row(
+            "SELECT time FROM blocks WHERE height = ?",
+            params![u32::from(height)],
+            |row| row.get(0),
+        )
+        .optional()?;
+    stmts.wallet_db.conn.execute(
+        "UPDATE transactions SET timestamp = COALESCE(timestamp, ?) WHERE id_tx = ?",
+        params![block_time, tx_ref],
+    )?;
+
+    Ok(tx_ref)
+}
+
+/// Minimal view of the data a scanned block's transaction needs to expose for
+/// [`put_tx_meta`].
+pub trait TxMeta {
+    fn txid(&self) -> TxId;
+    fn tx_index(&self) -> i64;
+}
+
+/// Decodes the human-readable text of a memo, if it is one; returns `None` for an empty,
+/// future, or binary memo, or for bytes that are not a valid memo at all.
+fn decode_memo_text(memo_bytes: &[u8]) -> Option<String> {
+    Memo::from_bytes(memo_bytes)
+        .ok()
+        .and_then(|memo| String::try_from(memo).ok())
+}
+
+/// Records, for the transaction referenced by `tx_ref`, which account it belongs to
+/// along with its counterparty address and decoded memo text (when known), so that
+/// [`get_tx_info`] can answer without re-deriving these fields from the raw transaction.
+/// An `address` or `memo` of `None` leaves whatever was previously recorded in place.
+fn set_tx_account_metadata<'a, P>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    tx_ref: i64,
+    account: AccountId,
+    address: Option<&str>,
+    memo: Option<&str>,
+) -> Result<(), SqliteClientError> {
+    stmts.wallet_db.conn.execute(
+        "UPDATE transactions
+        SET account = ?,
+            address = COALESCE(?, address),
+            memo = COALESCE(?, memo)
+        WHERE id_tx = ?",
+        params![account.0, address, memo, tx_ref],
+    )?;
+
+    Ok(())
+}
+
+/// Looks up which account owns the note with nullifier `nf`, so a mined spend
+/// can be recorded against that account's own `transactions` row. Returns `None` if no
+/// tracked note has this nullifier.
+pub fn get_account_for_nullifier<'a, P>(
+    stmts: &DataConnStmtCache<'a, P>,
+    nf: &Nullifier,
+) -> Result<Option<AccountId>, SqliteClientError> {
+    stmts
+        .wallet_db
+        .conn
+        .query_row(
+            "SELECT account FROM received_notes WHERE nf = ?",
+            params![&nf.0[..]],
+            |row| row.get::<_, u32>(0).map(AccountId),
+        )
+        .optional()
+        .map_err(SqliteClientError::from)
+}
+
+/// Marks a note as spent by the given transaction.
+pub fn mark_spent<'a, P>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    tx_ref: i64,
+    nf: &Nullifier,
+) -> Result<(), SqliteClientError> {
+    stmts
+        .stmt_mark_recived_note_spent
+        .execute(params![tx_ref, &nf.0[..]])?;
+    Ok(())
+}
+
+/// Records a note received by the wallet, updating it in place if it was already known.
+pub fn put_received_note<'a, P, T>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    output: &T,
+    tx_ref: i64,
+) -> Result<NoteId, SqliteClientError>
+where
+    T: ReceivedOutput,
+{
+    let rcm = output.note().rcm().to_repr();
+    let account = output.account().0;
+    let diversifier = output.to().diversifier().0.to_vec();
+    let value = output.note().value as i64;
+    let memo = output.memo().map(|m| m.as_slice().to_vec());
+    let is_change = output.is_change();
+    let nf = output.nullifier().map(|nf| nf.0.to_vec());
+
+    let sql_args = params![
+        tx_ref,
+        output.index() as i64,
+        account,
+        diversifier,
+        value,
+        &rcm[..],
+        memo,
+        nf,
+        is_change,
+    ];
+
+    stmts.stmt_update_received_note.execute(sql_args)?;
+
+    let id_note = stmts
+        .stmt_select_received_note
+        .query_row(params![tx_ref, output.index() as i64], |row| row.get(0))
+        .optional()?;
+
+    let id_note = match id_note {
+        Some(id_note) => id_note,
+        None => {
+            stmts.stmt_insert_received_note.execute(sql_args)?;
+            stmts.wallet_db.conn.last_insert_rowid()
+        }
+    };
+
+    let memo_text = output
+        .memo()
+        .and_then(|m| String::try_from(m.clone()).ok());
+    set_tx_account_metadata(stmts, tx_ref, output.account(), None, memo_text.as_deref())?;
+
+    Ok(NoteId::ReceivedNoteId(id_note))
+}
+
+/// Minimal view of a shielded output needed to persist it as a received note.
+pub trait ReceivedOutput {
+    fn index(&self) -> usize;
+    fn account(&self) -> AccountId;
+    fn to(&self) -> &PaymentAddress;
+    fn note(&self) -> &Note;
+    fn memo(&self) -> Option<&Memo>;
+    fn is_change(&self) -> bool;
+    fn nullifier(&self) -> Option<Nullifier>;
+}
+
+/// Records a witness for the given received note, as of the given block height.
+pub fn insert_witness<'a, P>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    note_id: i64,
+    witness: &IncrementalWitness<Node>,
+    height: BlockHeight,
+) -> Result<(), SqliteClientError> {
+    let mut encoded = Vec::new();
+    witness.write(&mut encoded).unwrap();
+
+    stmts
+        .stmt_insert_witness
+        .execute(params![note_id, u32::from(height), encoded])?;
+
+    Ok(())
+}
+
+/// Deletes witnesses older than the given height, which are no longer required to
+/// construct witness updates.
+pub fn prune_witnesses<'a, P>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    below_height: BlockHeight,
+) -> Result<(), SqliteClientError> {
+    stmts
+        .stmt_prune_witnesses
+        .execute(params![u32::from(below_height)])?;
+
+    // Record that witnesses below `below_height` are gone for good, so `rewind_to_height`
+    // can check against what pruning actually did even if `max_reorg_depth` is later
+    // raised. The recorded height only ever moves forward, since deletions can't be undone.
+    stmts.wallet_db.conn.execute(
+        "INSERT INTO witness_retention (id, min_retained_height) VALUES (0, ?)
+        ON CONFLICT (id) DO UPDATE SET
+            min_retained_height = MAX(min_retained_height, excluded.min_retained_height)",
+        params![u32::from(below_height)],
+    )?;
+
+    Ok(())
+}
+
+/// Returns the lowest height below which [`prune_witnesses`] has actually deleted
+/// `sapling_witnesses` rows, or `None` if it has never run. See [`rewind_to_height`].
+pub fn get_min_retained_witness_height<P>(
+    wdb: &WalletDb<P>,
+) -> Result<Option<BlockHeight>, SqliteClientError> {
+    Ok(wdb
+        .conn
+        .query_row(
+            "SELECT min_retained_height FROM witness_retention WHERE id = 0",
+            [],
+            |row| row.get::<_, Option<u32>>(0),
+        )
+        .optional()?
+        .flatten()
+        .map(BlockHeight::from))
+}
+
+/// Marks notes whose spending transaction expired without being mined as unspent again.
+pub fn update_expired_notes<'a, P>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    height: BlockHeight,
+) -> Result<(), SqliteClientError> {
+    stmts
+        .stmt_update_expired
+        .execute(params![u32::from(height)])?;
+    Ok(())
+}
+
+/// Looks up (or inserts) the internal row ID for a transaction that the wallet created or
+/// decrypted, scoped to `account`, recording its raw bytes and expiry height. As with
+/// [`put_tx_meta`], a transaction touching more than one of the wallet's own accounts gets
+/// a separate row per account; callers must invoke this once per distinct account.
+pub fn put_tx_data<'a, P>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    tx: &zcash_primitives::transaction::Transaction,
+    created_at: Option<time::OffsetDateTime>,
+    account: AccountId,
+) -> Result<i64, SqliteClientError> {
+    let txid = tx.txid().as_ref().to_vec();
+    let mut raw_tx = vec![];
+    tx.write(&mut raw_tx).unwrap();
+
+    let tx_ref = if let Some(id_tx) = stmts
+        .stmt_select_tx_ref
+        .query_row(params![txid, account.0], |row| row.get(0))
+        .optional()?
+    {
+        stmts.stmt_update_tx_data.execute(params![
+            u32::from(tx.expiry_height),
+            raw_tx,
+            tx.txid().as_ref().to_vec(),
+            account.0,
+        ])?;
+        id_tx
+    } else {
+        stmts.stmt_insert_tx_data.execute(params![
+            tx.txid().as_ref().to_vec(),
+            created_at.map(|t| t.unix_timestamp()),
+            u32::from(tx.expiry_height),
+            raw_tx,
+            account.0,
+        ])?;
+        stmts.wallet_db.conn.last_insert_rowid()
+    };
+
+    // Display timestamps default to when we created this transaction; `put_tx_meta`
+    // will have already set one from the mined block's time if it ran first.
+    stmts.wallet_db.conn.execute(
+        "UPDATE transactions SET timestamp = COALESCE(timestamp, ?) WHERE id_tx = ?",
+        params![created_at.map(|t| t.unix_timestamp()), tx_ref],
+    )?;
+
+    Ok(tx_ref)
+}
+
+/// Recognizes and records any outputs of `tx` that pay a transparent address the wallet
+/// has derived for one of its accounts, so that autoshielding and [`get_balance_at`] see
+/// transparent receipts (including our own change) the same way shielded scanning already
+/// does for Sapling notes. Called from `store_received_tx`/`store_sent_tx` once they have a
+/// transaction's full `vout` in hand.
+///
+/// Each output is recorded at the height `tx` is known to have been mined at, if any.
+/// Outputs of a transaction not yet known to be mined are recorded without a height, and
+/// are not counted by [`get_balance_at`] until they are recorded again with one.
+///
+/// Only reachable for transactions the wallet already has in hand as a full
+/// [`zcash_primitives::transaction::Transaction`]: [`CompactBlock`], which
+/// `advance_by_block_inner` scans, omits transparent transaction data entirely, so there is
+/// no way to recognize transparent outputs from a compact-block scan alone.
+///
+/// [`CompactBlock`]: zcash_client_backend::proto::compact_formats::CompactBlock
+pub fn record_transparent_outputs<'a, P: consensus::Parameters>(
+    stmts: &DataConnStmtCache<'a, P>,
+    tx: &zcash_primitives::transaction::Transaction,
+) -> Result<(), SqliteClientError> {
+    let mut txid = [0u8; 32];
+    txid.copy_from_slice(tx.txid().as_ref());
+    let height = get_tx_height(stmts.wallet_db, tx.txid())?;
+
+    for (n, txout) in tx.vout.iter().enumerate() {
+        if let Some((_, address)) =
+            transparent::find_account_for_transparent_output(stmts.wallet_db, &txout.script_pubkey)?
+        {
+            let outpoint = zcash_primitives::transaction::components::OutPoint::new(txid, n as u32);
+            transparent::put_received_transparent_utxo(stmts.wallet_db, &address, &outpoint, txout, height)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Records a note sent by the wallet, either updating it in place or inserting a new row.
+#[allow(clippy::too_many_arguments)]
+pub fn insert_sent_note<'a, P: consensus::Parameters>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    tx_ref: i64,
+    output_index: usize,
+    account: AccountId,
+    to: &zcash_client_backend::address::RecipientAddress,
+    value: Amount,
+    memo: Option<&zcash_primitives::memo::MemoBytes>,
+) -> Result<(), SqliteClientError> {
+    use zcash_client_backend::encoding::encode_payment_address;
+    let to_str = match to {
+        zcash_client_backend::address::RecipientAddress::Shielded(addr) => {
+            encode_payment_address(stmts.wallet_db.params.hrp_sapling_payment_address(), addr)
+        }
+        zcash_client_backend::address::RecipientAddress::Transparent(addr) => addr.to_string(),
+    };
+
+    let ua = stmts.stmt_update_sent_note.execute(params![
+        account.0,
+        to_str,
+        i64::from(value),
+        memo.map(|m| m.as_slice().to_vec()),
+        tx_ref,
+        output_index as i64,
+    ])?;
+
+    if ua == 0 {
+        stmts.stmt_insert_sent_note.execute(params![
+            tx_ref,
+            output_index as i64,
+            account.0,
+            to_str,
+            i64::from(value),
+            memo.map(|m| m.as_slice().to_vec()),
+        ])?;
+    }
+
+    let memo_text = memo.and_then(|m| decode_memo_text(m.as_slice()));
+    set_tx_account_metadata(stmts, tx_ref, account, Some(&to_str), memo_text.as_deref())?;
+
+    Ok(())
+}
+
+/// Minimal view of a shielded output discovered (e.g. via trial decryption against our
+/// own outgoing viewing key) to have been sent by one of our own accounts, as opposed to
+/// a payment this wallet is actively constructing via [`insert_sent_note`].
+pub trait SentOutput {
+    fn index(&self) -> usize;
+    fn account(&self) -> AccountId;
+    fn to(&self) -> &zcash_client_backend::address::RecipientAddress;
+    fn value(&self) -> Amount;
+    fn memo(&self) -> Option<&zcash_primitives::memo::MemoBytes>;
+}
+
+/// Records an output discovered to have been sent by one of our own accounts, delegating
+/// to the same upsert logic as [`insert_sent_note`].
+pub fn put_sent_note<'a, P: consensus::Parameters, T: SentOutput>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    output: &T,
+    tx_ref: i64,
+) -> Result<(), SqliteClientError> {
+    insert_sent_note(
+        stmts,
+        tx_ref,
+        output.index(),
+        output.account(),
+        output.to(),
+        output.value(),
+        output.memo(),
+    )
+}
+
+/// Rewinds the wallet database to the given height, deleting any data recorded for
+/// later blocks and clearing the spent/pruned state of notes that are affected.
+pub fn rewind_to_height<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    block_height: BlockHeight,
+) -> Result<(), SqliteClientError> {
+    let sapling_activation_height = wdb
+        .params
+        .activation_height(consensus::NetworkUpgrade::Sapling)
+        .ok_or_else(|| SqliteClientError::CorruptedData("Sapling activation height unknown".into()))?;
+
+    // Recall where we synced up to previously.
+    let last_scanned_height = wdb
+        .conn
+        .query_row("SELECT MAX(height) FROM blocks", [], |row| {
+            row.get(0)
+                .map(|h: Option<u32>| h.map_or(sapling_activation_height - 1, BlockHeight::from))
+        })?;
+
+    if block_height >= last_scanned_height {
+        // Nothing to do.
+        return Ok(());
+    }
+
+    // We only retain enough witness history to roll back through `max_reorg_depth`
+    // blocks; a rewind past that horizon would leave notes mined before it without a
+    // usable witness. This is also bounded below by `min_retained_witness_height`, the
+    // height pruning has actually deleted witnesses back to -- which may be lower than
+    // what the *current* `max_reorg_depth` would compute if it was raised after that
+    // pruning already ran, so the live computation alone isn't a safe bound on its own.
+    let configured_min_rewind_height = if last_scanned_height < BlockHeight::from(wdb.max_reorg_depth) {
+        BlockHeight::from(0)
+    } else {
+        last_scanned_height - wdb.max_reorg_depth
+    };
+    let actually_retained_height =
+        get_min_retained_witness_height(wdb)?.unwrap_or_else(|| BlockHeight::from(0));
+    let min_rewind_height = configured_min_rewind_height.max(actually_retained_height);
+    if block_height < min_rewind_height {
+        return Err(SqliteClientError::CorruptedData(format!(
+            "Cannot rewind to height {}: witness history is only retained back to height {}",
+            u32::from(block_height),
+            u32::from(min_rewind_height),
+        )));
+    }
+
+    wdb.conn.execute("BEGIN IMMEDIATE", [])?;
+    wdb.conn.execute(
+        "DELETE FROM blocks WHERE height > ?",
+        params![u32::from(block_height)],
+    )?;
+    wdb.conn.execute(
+        "DELETE FROM transactions WHERE block IS NOT NULL AND block > ?",
+        params![u32::from(block_height)],
+    )?;
+    wdb.conn.execute(
+        "DELETE FROM sapling_witnesses WHERE block > ?",
+        params![u32::from(block_height)],
+    )?;
+    wdb.conn.execute(
+        "UPDATE received_notes SET spent = NULL WHERE spent IN (
+            SELECT id_tx FROM transactions WHERE block IS NULL
+        )",
+        [],
+    )?;
+    wdb.conn.execute("COMMIT", [])?;
+
+    Ok(())
+}
+
+/// A transaction summary suitable for display in a wallet UI, assembled from the
+/// denormalized fields on its `transactions` row rather than by re-deriving them from the
+/// raw transaction.
+#[derive(Debug, Clone)]
+pub struct TransactionInfo {
+    pub txid: TxId,
+    pub height: Option<BlockHeight>,
+    pub timestamp: Option<i64>,
+    pub value: Amount,
+    pub address: Option<String>,
+    pub memo: Option<String>,
+}
+
+/// Returns display metadata for the transaction referenced by `tx_ref`: its value is the
+/// total of its `sent_notes` if it has any, and otherwise the total of its
+/// `received_notes`. For a send that also produced change, the change is not counted.
+pub fn get_tx_info<P>(
+    wdb: &WalletDb<P>,
+    tx_ref: i64,
+) -> Result<TransactionInfo, SqliteClientError> {
+    wdb.conn
+        .query_row(
+            "SELECT
+                t.txid,
+                t.block,
+                t.timestamp,
+                t.address,
+                t.memo,
+                COALESCE(
+                    (SELECT SUM(value) FROM sent_notes WHERE tx = t.id_tx),
+                    (SELECT SUM(value) FROM received_notes WHERE tx = t.id_tx),
+                    0
+                ) AS value
+            FROM transactions t
+            WHERE t.id_tx = ?",
+            params![tx_ref],
+            |row| {
+                let txid: Vec<u8> = row.get(0)?;
+                let height: Option<u32> = row.get(1)?;
+                let timestamp: Option<i64> = row.get(2)?;
+                let address: Option<String> = row.get(3)?;
+                let memo: Option<String> = row.get(4)?;
+                let value: i64 = row.get(5)?;
+                Ok((txid, height, timestamp, address, memo, value))
+            },
+        )
+        .map_err(SqliteClientError::from)
+        .and_then(|(txid, height, timestamp, address, memo, value)| {
+            let mut txid_bytes = [0u8; 32];
+            txid_bytes.copy_from_slice(&txid);
+
+            Ok(TransactionInfo {
+                txid: TxId::from_bytes(txid_bytes),
+                height: height.map(BlockHeight::from),
+                timestamp,
+                address,
+                memo,
+                value: Amount::from_i64(value).map_err(|_| {
+                    SqliteClientError::CorruptedData(
+                        "Sum of values in sent_notes or received_notes is out of range".to_string(),
+                    )
+                })?,
+            })
+        })
+}
@@ -0,0 +1,102 @@
+//! Diversified address generation.
+//!
+//! Each account's `accounts.diversifier_index_offset` column tracks the lowest
+//! diversifier index that has not yet been handed out to a caller. A fresh address is
+//! produced by asking the account's [`ExtendedFullViewingKey`] for the first valid
+//! diversifier at or after that index (`ExtendedFullViewingKey::address` walks forward
+//! over indices whose diversifier does not produce a valid [`PaymentAddress`]), then
+//! persisting the index immediately following the one that was used so the same
+//! address is never handed out twice.
+
+use rusqlite::{params, OptionalExtension};
+
+use zcash_client_backend::wallet::AccountId;
+use zcash_primitives::{sapling::PaymentAddress, zip32::DiversifierIndex};
+
+use crate::{error::SqliteClientError, wallet::get_extended_full_viewing_keys, WalletDb};
+use zcash_primitives::consensus;
+
+/// Returns the stored diversifier index offset for `account`, defaulting to the first
+/// index if none has been persisted yet.
+fn get_diversifier_index_offset<P>(
+    wdb: &WalletDb<P>,
+    account: AccountId,
+) -> Result<DiversifierIndex, SqliteClientError> {
+    let stored: Option<Vec<u8>> = wdb
+        .conn
+        .query_row(
+            "SELECT diversifier_index_offset FROM accounts WHERE account = ?",
+            params![account.0],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+
+    Ok(match stored {
+        Some(bytes) if bytes.len() == 11 => {
+            let mut idx = [0u8; 11];
+            idx.copy_from_slice(&bytes);
+            DiversifierIndex(idx)
+        }
+        _ => DiversifierIndex::new(),
+    })
+}
+
+fn set_diversifier_index_offset<P>(
+    wdb: &WalletDb<P>,
+    account: AccountId,
+    index: &DiversifierIndex,
+) -> Result<(), SqliteClientError> {
+    wdb.conn.execute(
+        "UPDATE accounts SET diversifier_index_offset = ? WHERE account = ?",
+        params![index.0.to_vec(), account.0],
+    )?;
+
+    Ok(())
+}
+
+/// Returns the current diversified address for `account` without advancing the stored
+/// index, i.e. the address that the next call to [`get_next_diversified_address`]
+/// would also return.
+pub fn get_current_diversified_address<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    account: AccountId,
+) -> Result<PaymentAddress, SqliteClientError> {
+    let extfvk = get_extended_full_viewing_keys(wdb)?
+        .remove(&account)
+        .ok_or(SqliteClientError::IncorrectHrpExtFvk)?;
+
+    let offset = get_diversifier_index_offset(wdb, account)?;
+    let (_, addr) = extfvk
+        .address(offset)
+        .map_err(|()| SqliteClientError::CorruptedData("Diversifier index space exhausted".to_owned()))?;
+
+    Ok(addr)
+}
+
+/// Derives, persists, and returns a fresh diversified address for `account`: the
+/// smallest diversifier index at or after the stored offset that yields a valid
+/// address, after which the stored offset is advanced past it so this address is never
+/// handed out again.
+pub fn get_next_diversified_address<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    account: AccountId,
+) -> Result<PaymentAddress, SqliteClientError> {
+    let extfvk = get_extended_full_viewing_keys(wdb)?
+        .remove(&account)
+        .ok_or(SqliteClientError::IncorrectHrpExtFvk)?;
+
+    let offset = get_diversifier_index_offset(wdb, account)?;
+    let (used_index, addr) = extfvk
+        .address(offset)
+        .map_err(|()| SqliteClientError::CorruptedData("Diversifier index space exhausted".to_owned()))?;
+
+    let mut next_index = used_index;
+    next_index
+        .increment()
+        .map_err(|()| SqliteClientError::CorruptedData("Diversifier index space exhausted".to_owned()))?;
+
+    set_diversifier_index_offset(wdb, account, &next_index)?;
+
+    Ok(addr)
+}
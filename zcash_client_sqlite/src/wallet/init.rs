@@ -0,0 +1,356 @@
+//! Functions for initializing the data database, migrating it forward across schema
+//! versions, and adding accounts to the wallet.
+//!
+//! # Schema migrations
+//!
+//! The data database's structure is versioned by a single-row `schema_version` table.
+//! Each release of this crate that changes the schema adds an entry to [`MIGRATIONS`]: a
+//! closure that takes the current schema (at the version immediately below it) to the
+//! next version. [`init_wallet_db`] reads the stored version, then applies only the
+//! migrations between that version and [`SCHEMA_VERSION`], each inside its own
+//! transaction, so that a process that crashes partway through an upgrade leaves the
+//! database at a consistent (if outdated) version rather than a half-migrated one.
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use zcash_client_backend::encoding::encode_extended_full_viewing_key;
+use zcash_primitives::{consensus, zip32::ExtendedFullViewingKey};
+
+use crate::{error::SqliteClientError, WalletDb};
+
+/// The schema version this version of the crate knows how to read and write.
+pub const SCHEMA_VERSION: u32 = 6;
+
+/// The ordered list of migrations that bring a data database from schema version `i`
+/// to schema version `i + 1`, for `i` starting at 0. `MIGRATIONS[0]` creates the
+/// version-1 schema from an empty database; later entries will be appended here as the
+/// schema evolves.
+const MIGRATIONS: &[fn(&Connection) -> Result<(), SqliteClientError>] = &[
+    create_base_schema,
+    create_transparent_address_schema,
+    add_diversifier_index_column,
+    add_transaction_metadata_columns,
+    rename_utxos_table,
+    create_witness_retention_table,
+];
+
+/// Reads the schema version currently recorded in the database, treating a missing
+/// `schema_version` table (i.e. a brand new database) as version 0.
+pub fn get_schema_version<P>(wdb: &WalletDb<P>) -> Result<u32, SqliteClientError> {
+    get_schema_version_conn(&wdb.conn)
+}
+
+fn get_schema_version_conn(conn: &Connection) -> Result<u32, SqliteClientError> {
+    let exists = conn
+        .prepare("SELECT * FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'")?
+        .exists([])?;
+
+    if !exists {
+        return Ok(0);
+    }
+
+    Ok(conn
+        .query_row("SELECT version FROM schema_version WHERE id = 0", [], |row| {
+            row.get(0)
+        })
+        .optional()?
+        .unwrap_or(0))
+}
+
+/// Records `version` as the database's current schema version, creating the
+/// `schema_version` table first if necessary.
+pub fn update_schema_version<P>(wdb: &WalletDb<P>, version: u32) -> Result<(), SqliteClientError> {
+    update_schema_version_conn(&wdb.conn, version)
+}
+
+fn update_schema_version_conn(conn: &Connection, version: u32) -> Result<(), SqliteClientError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            id INTEGER PRIMARY KEY,
+            version INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO schema_version (id, version) VALUES (0, ?)
+        ON CONFLICT (id) DO UPDATE SET version = excluded.version",
+        params![version],
+    )?;
+
+    Ok(())
+}
+
+/// Sets up the internal structure of the data database, creating the tables the rest of
+/// this crate relies on if they do not already exist, and running any schema migrations
+/// that have not yet been applied.
+///
+/// This should be called at wallet startup, before any other operation is performed
+/// against the database. It is safe to call on an already-initialized database of an
+/// older (but supported) schema version; it is a no-op on a database already at
+/// [`SCHEMA_VERSION`].
+pub fn init_wallet_db<P>(wdb: &WalletDb<P>) -> Result<(), SqliteClientError> {
+    let current_version = get_schema_version_conn(&wdb.conn)?;
+
+    if current_version > SCHEMA_VERSION {
+        return Err(SqliteClientError::UnsupportedSchemaVersion(
+            current_version,
+            SCHEMA_VERSION,
+        ));
+    }
+
+    for (version, migration) in MIGRATIONS.iter().enumerate() {
+        let target_version = (version + 1) as u32;
+        if target_version <= current_version {
+            continue;
+        }
+
+        wdb.conn.execute("BEGIN IMMEDIATE", [])?;
+        let result = migration(&wdb.conn).and_then(|()| {
+            update_schema_version_conn(&wdb.conn, target_version)
+        });
+        match result {
+            Ok(()) => wdb.conn.execute("COMMIT", [])?,
+            Err(e) => {
+                wdb.conn.execute("ROLLBACK", [])?;
+                return Err(e);
+            }
+        };
+    }
+
+    Ok(())
+}
+
+/// Migration 0 -> 1: creates the base wallet schema used by every table in this crate.
+fn create_base_schema(conn: &Connection) -> Result<(), SqliteClientError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS accounts (
+            account INTEGER PRIMARY KEY,
+            extfvk TEXT NOT NULL,
+            address TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS blocks (
+            height INTEGER PRIMARY KEY,
+            hash BLOB NOT NULL,
+            time INTEGER NOT NULL,
+            sapling_tree BLOB NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS transactions (
+            id_tx INTEGER PRIMARY KEY,
+            txid BLOB NOT NULL UNIQUE,
+            created TEXT,
+            block INTEGER,
+            tx_index INTEGER,
+            expiry_height INTEGER,
+            raw BLOB,
+            FOREIGN KEY (block) REFERENCES blocks(height)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS received_notes (
+            id_note INTEGER PRIMARY KEY,
+            tx INTEGER NOT NULL,
+            output_index INTEGER NOT NULL,
+            account INTEGER NOT NULL,
+            diversifier BLOB NOT NULL,
+            value INTEGER NOT NULL,
+            rcm BLOB NOT NULL,
+            nf BLOB UNIQUE,
+            is_change BOOLEAN NOT NULL,
+            memo BLOB,
+            spent INTEGER,
+            FOREIGN KEY (tx) REFERENCES transactions(id_tx),
+            FOREIGN KEY (account) REFERENCES accounts(account),
+            FOREIGN KEY (spent) REFERENCES transactions(id_tx),
+            CONSTRAINT tx_output UNIQUE (tx, output_index)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sapling_witnesses (
+            id_witness INTEGER PRIMARY KEY,
+            note INTEGER NOT NULL,
+            block INTEGER NOT NULL,
+            witness BLOB NOT NULL,
+            FOREIGN KEY (note) REFERENCES received_notes(id_note),
+            FOREIGN KEY (block) REFERENCES blocks(height),
+            CONSTRAINT witness_height UNIQUE (note, block)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sent_notes (
+            id_note INTEGER PRIMARY KEY,
+            tx INTEGER NOT NULL,
+            output_index INTEGER NOT NULL,
+            from_account INTEGER NOT NULL,
+            address TEXT NOT NULL,
+            value INTEGER NOT NULL,
+            memo BLOB,
+            FOREIGN KEY (tx) REFERENCES transactions(id_tx),
+            FOREIGN KEY (from_account) REFERENCES accounts(account),
+            CONSTRAINT tx_output UNIQUE (tx, output_index)
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Migration 1 -> 2: adds storage for transparent receiving addresses, so that
+/// wallets can track a t-address per account alongside its Sapling address.
+fn create_transparent_address_schema(conn: &Connection) -> Result<(), SqliteClientError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS transparent_addresses (
+            account INTEGER PRIMARY KEY,
+            address TEXT NOT NULL UNIQUE,
+            FOREIGN KEY (account) REFERENCES accounts(account)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS utxos (
+            id_utxo INTEGER PRIMARY KEY,
+            address TEXT NOT NULL,
+            prevout_txid BLOB NOT NULL,
+            prevout_idx INTEGER NOT NULL,
+            script BLOB NOT NULL,
+            value_satoshis INTEGER NOT NULL,
+            height INTEGER,
+            spent_in_tx INTEGER,
+            FOREIGN KEY (spent_in_tx) REFERENCES transactions(id_tx),
+            CONSTRAINT utxo_outpoint UNIQUE (prevout_txid, prevout_idx)
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Migration 2 -> 3: adds a column tracking the last-used diversifier index per
+/// account, so diversified addresses handed out via `get_next_diversified_address` are
+/// never repeated.
+fn add_diversifier_index_column(conn: &Connection) -> Result<(), SqliteClientError> {
+    conn.execute(
+        "ALTER TABLE accounts ADD COLUMN diversifier_index_offset BLOB",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Migration 3 -> 4: adds columns recording, for each transaction, the account it was
+/// written for, its counterparty address, a decoded memo, and a display timestamp, so a
+/// wallet UI can build a transaction list without re-deriving these fields by re-scanning
+/// the raw transaction. Also relaxes the `txid` uniqueness constraint from a bare
+/// `UNIQUE` to `UNIQUE (block, tx_index, account)`, since a single mined transaction can
+/// touch more than one of the wallet's own accounts.
+///
+/// SQLite cannot alter a table's `UNIQUE` constraints in place, so the table is rebuilt:
+/// renamed aside, recreated with the new shape, repopulated from the old rows (with the
+/// new columns left `NULL`, to be backfilled as each transaction's notes are processed),
+/// then the old table is dropped.
+///
+/// With `legacy_alter_table` off (SQLite's default since 3.25.0), `ALTER TABLE ... RENAME`
+/// also rewrites every other table's `FOREIGN KEY` clauses that reference the renamed
+/// table, so `received_notes.tx`, `sent_notes.tx`, and `utxos.spent_in_tx` would end up
+/// referencing `transactions_old` instead of the recreated `transactions` table, and be
+/// left dangling once it is dropped. `legacy_alter_table` is turned on for the duration of
+/// the rebuild so the rename leaves their FK clauses referring to the literal name
+/// `transactions`, which is valid again once the new table is created under that name.
+fn add_transaction_metadata_columns(conn: &Connection) -> Result<(), SqliteClientError> {
+    conn.pragma_update(None, "legacy_alter_table", &true)?;
+    conn.execute("ALTER TABLE transactions RENAME TO transactions_old", [])?;
+    conn.execute(
+        "CREATE TABLE transactions (
+            id_tx INTEGER PRIMARY KEY,
+            txid BLOB NOT NULL,
+            created TEXT,
+            block INTEGER,
+            tx_index INTEGER,
+            expiry_height INTEGER,
+            raw BLOB,
+            account INTEGER,
+            address TEXT,
+            memo TEXT,
+            timestamp INTEGER,
+            FOREIGN KEY (block) REFERENCES blocks(height),
+            FOREIGN KEY (account) REFERENCES accounts(account),
+            CONSTRAINT tx_height_index_account UNIQUE (block, tx_index, account)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "INSERT INTO transactions (id_tx, txid, created, block, tx_index, expiry_height, raw)
+        SELECT id_tx, txid, created, block, tx_index, expiry_height, raw FROM transactions_old",
+        [],
+    )?;
+    conn.execute("DROP TABLE transactions_old", [])?;
+    conn.pragma_update(None, "legacy_alter_table", &false)?;
+
+    Ok(())
+}
+
+/// Migration 4 -> 5: renames the `utxos` table to `transparent_received_outputs`, to
+/// match the naming of the shielded `received_notes` table it mirrors now that
+/// transparent outputs are tracked for autoshielding rather than just balance display.
+fn rename_utxos_table(conn: &Connection) -> Result<(), SqliteClientError> {
+    conn.execute(
+        "ALTER TABLE utxos RENAME TO transparent_received_outputs",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Migration 5 -> 6: adds a single-row table recording the lowest height below which
+/// [`wallet::prune_witnesses`] has actually deleted `sapling_witnesses` rows. Unlike
+/// `max_reorg_depth`, which a caller can raise at any time, this records what pruning
+/// already did, so [`wallet::rewind_to_height`] can refuse a rewind past witness data
+/// that's genuinely gone even if `max_reorg_depth` has since been raised.
+///
+/// [`wallet::prune_witnesses`]: crate::wallet::prune_witnesses
+/// [`wallet::rewind_to_height`]: crate::wallet::rewind_to_height
+fn create_witness_retention_table(conn: &Connection) -> Result<(), SqliteClientError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS witness_retention (
+            id INTEGER PRIMARY KEY,
+            min_retained_height INTEGER
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Initializes the accounts table with the given extended full viewing keys, computing
+/// and storing the default address for each account.
+pub fn init_accounts_table<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    extfvks: &[ExtendedFullViewingKey],
+) -> Result<(), SqliteClientError> {
+    let mut empty_check = wdb.conn.prepare("SELECT * FROM accounts LIMIT 1")?;
+    if empty_check.exists([])? {
+        return Err(SqliteClientError::TableNotEmpty);
+    }
+
+    for (account, extfvk) in extfvks.iter().enumerate() {
+        let address = crate::address_from_extfvk(&wdb.params, extfvk);
+        let extfvk_str =
+            encode_extended_full_viewing_key(wdb.params.hrp_sapling_extended_full_viewing_key(), extfvk);
+
+        wdb.conn.execute(
+            "INSERT INTO accounts (account, extfvk, address)
+            VALUES (?, ?, ?)",
+            params![account as u32, extfvk_str, address],
+        )?;
+    }
+
+    Ok(())
+}
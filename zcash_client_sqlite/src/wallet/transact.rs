@@ -0,0 +1,124 @@
+//! Functions for selecting notes to spend, used by [`WalletRead::get_spendable_notes`] and
+//! [`WalletRead::select_spendable_notes`].
+//!
+//! [`WalletRead::get_spendable_notes`]: zcash_client_backend::data_api::WalletRead::get_spendable_notes
+//! [`WalletRead::select_spendable_notes`]: zcash_client_backend::data_api::WalletRead::select_spendable_notes
+
+use rusqlite::params;
+
+use zcash_client_backend::wallet::{AccountId, SpendableNote};
+use zcash_primitives::{
+    consensus::BlockHeight,
+    merkle_tree::IncrementalWitness,
+    sapling::{Diversifier, Node, Rseed},
+    transaction::components::Amount,
+};
+
+use crate::{error::SqliteClientError, WalletDb};
+
+/// Returns every unspent, confirmed note belonging to the given account as of the given
+/// anchor height, without regard to how much total value is required.
+pub fn get_spendable_notes<P>(
+    wdb: &WalletDb<P>,
+    account: AccountId,
+    anchor_height: BlockHeight,
+) -> Result<Vec<SpendableNote>, SqliteClientError> {
+    let mut stmt_select_notes = wdb.conn.prepare(
+        "SELECT diversifier, value, rcm, witness
+        FROM received_notes
+        INNER JOIN transactions ON transactions.id_tx = received_notes.tx
+        INNER JOIN sapling_witnesses ON sapling_witnesses.note = received_notes.id_note
+        WHERE received_notes.account = ?
+        AND received_notes.spent IS NULL
+        AND transactions.block <= ?
+        AND sapling_witnesses.block = ?",
+    )?;
+
+    query_spendable_notes(
+        &mut stmt_select_notes,
+        account,
+        anchor_height,
+    )
+}
+
+/// Returns notes belonging to the given account, selected greedily until at least
+/// `target_value` has been accumulated, as of the given anchor height.
+pub fn select_spendable_notes<P>(
+    wdb: &WalletDb<P>,
+    account: AccountId,
+    target_value: Amount,
+    anchor_height: BlockHeight,
+) -> Result<Vec<SpendableNote>, SqliteClientError> {
+    let mut stmt_select_notes = wdb.conn.prepare(
+        "SELECT diversifier, value, rcm, witness
+        FROM received_notes
+        INNER JOIN transactions ON transactions.id_tx = received_notes.tx
+        INNER JOIN sapling_witnesses ON sapling_witnesses.note = received_notes.id_note
+        WHERE received_notes.account = ?
+        AND received_notes.spent IS NULL
+        AND transactions.block <= ?
+        AND sapling_witnesses.block = ?
+        ORDER BY received_notes.value DESC",
+    )?;
+
+    let candidates = query_spendable_notes(&mut stmt_select_notes, account, anchor_height)?;
+
+    let mut selected = Vec::new();
+    let mut selected_value = Amount::zero();
+    for note in candidates {
+        if selected_value >= target_value {
+            break;
+        }
+        selected_value += note.note_value;
+        selected.push(note);
+    }
+
+    Ok(selected)
+}
+
+fn query_spendable_notes<P>(
+    stmt_select_notes: &mut rusqlite::Statement,
+    account: AccountId,
+    anchor_height: BlockHeight,
+) -> Result<Vec<SpendableNote>, SqliteClientError> {
+    let rows = stmt_select_notes.query_map(
+        params![account.0, u32::from(anchor_height), u32::from(anchor_height)],
+        |row| {
+            let diversifier: Vec<u8> = row.get(0)?;
+            let note_value: i64 = row.get(1)?;
+            let rcm_bytes: Vec<u8> = row.get(2)?;
+            let witness_bytes: Vec<u8> = row.get(3)?;
+            Ok((diversifier, note_value, rcm_bytes, witness_bytes))
+        },
+    )?;
+
+    let mut notes = vec![];
+    for row in rows {
+        let (diversifier_bytes, note_value, rcm_bytes, witness_bytes) = row?;
+
+        let mut d = [0u8; 11];
+        d.copy_from_slice(&diversifier_bytes);
+        let diversifier = Diversifier(d);
+
+        let mut r = [0u8; 32];
+        r.copy_from_slice(&rcm_bytes);
+        let rseed = Rseed::BeforeZip212(
+            jubjub::Fr::from_bytes(&r)
+                .into_option()
+                .ok_or_else(|| SqliteClientError::CorruptedData("Invalid rcm".to_owned()))?,
+        );
+
+        let witness = IncrementalWitness::<Node>::read(&witness_bytes[..])
+            .map_err(|e| SqliteClientError::CorruptedData(e.to_string()))?;
+
+        notes.push(SpendableNote {
+            diversifier,
+            note_value: Amount::from_i64(note_value)
+                .map_err(|_| SqliteClientError::CorruptedData("Note value out of range".to_owned()))?,
+            rseed,
+            witness,
+        });
+    }
+
+    Ok(notes)
+}
@@ -0,0 +1,244 @@
+//! Transparent (t-address) key derivation and storage.
+//!
+//! Transparent receiving addresses are derived deterministically from the wallet seed
+//! via a BIP44 external path, `m/44'/coin_type'/account'/0/index`, mirroring how the
+//! Sapling extended full viewing key is derived per account. Unlike Sapling addresses,
+//! which are looked up from a diversifier, a wallet only ever hands out one transparent
+//! address per account (`index` 0), and the `transparent_addresses` table is keyed on
+//! `account`, so it holds at most one address per account.
+
+use rusqlite::{params, OptionalExtension};
+
+use zcash_client_backend::wallet::AccountId;
+use zcash_primitives::{
+    consensus::{self, BlockHeight},
+    legacy::{
+        keys::{AccountPrivKey, TransparentKeyScope},
+        TransparentAddress,
+    },
+    transaction::components::{Amount, OutPoint, TxOut},
+};
+
+use crate::{error::SqliteClientError, WalletDb};
+
+/// A transparent output the wallet controls, as read back from the
+/// `transparent_received_outputs` table.
+#[derive(Debug, Clone)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub txout: TxOut,
+    pub height: Option<BlockHeight>,
+}
+
+/// Derives the transparent address the wallet hands out for `account`, using the
+/// external (receiving) chain at index 0 of the BIP44 path for `account`.
+pub fn derive_transparent_address<P: consensus::Parameters>(
+    params: &P,
+    seed: &[u8],
+    account: AccountId,
+) -> Result<TransparentAddress, SqliteClientError> {
+    let account_key = AccountPrivKey::from_seed(params, seed, account)
+        .map_err(|_| SqliteClientError::CorruptedData("Invalid seed for transparent key derivation".to_owned()))?;
+
+    let external_key = account_key
+        .derive_external_secret_key(0)
+        .map_err(|_| SqliteClientError::CorruptedData("Transparent key derivation failed".to_owned()))?;
+
+    Ok(external_key.to_address())
+}
+
+/// Returns the transparent address stored for `account`, if one has been derived and
+/// persisted for it yet.
+pub fn get_transparent_address<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    account: AccountId,
+) -> Result<Option<TransparentAddress>, SqliteClientError> {
+    let addr: Option<String> = wdb
+        .conn
+        .query_row(
+            "SELECT address FROM transparent_addresses WHERE account = ?",
+            params![account.0],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    addr.map(|addr| {
+        TransparentAddress::decode(&wdb.params, &addr)
+            .map_err(|_| SqliteClientError::CorruptedData("Not a valid transparent address".to_owned()))
+    })
+    .transpose()
+}
+
+/// Returns the account and decoded address that own `script_pubkey`, if it pays one of
+/// the transparent addresses this wallet has derived for any account. Used by the scan
+/// path to recognize transparent outputs (including our own change) without already
+/// knowing which account, if any, to check.
+pub fn find_account_for_transparent_output<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    script_pubkey: &zcash_primitives::legacy::Script,
+) -> Result<Option<(AccountId, TransparentAddress)>, SqliteClientError> {
+    let mut stmt = wdb
+        .conn
+        .prepare("SELECT account, address FROM transparent_addresses")?;
+    let rows = stmt.query_map([], |row| {
+        let account: u32 = row.get(0)?;
+        let address: String = row.get(1)?;
+        Ok((AccountId(account), address))
+    })?;
+
+    for row in rows {
+        let (account, address) = row?;
+        let addr = TransparentAddress::decode(&wdb.params, &address).map_err(|_| {
+            SqliteClientError::CorruptedData("Not a valid transparent address".to_owned())
+        })?;
+        if &addr.script() == script_pubkey {
+            return Ok(Some((account, addr)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Derives (if necessary) and persists the transparent address for `account`, returning
+/// it either way.
+pub fn get_or_create_transparent_address<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    seed: &[u8],
+    account: AccountId,
+) -> Result<TransparentAddress, SqliteClientError> {
+    if let Some(addr) = get_transparent_address(wdb, account)? {
+        return Ok(addr);
+    }
+
+    let addr = derive_transparent_address(&wdb.params, seed, account)?;
+    wdb.conn.execute(
+        "INSERT INTO transparent_addresses (account, address) VALUES (?, ?)",
+        params![account.0, addr.encode(&wdb.params)],
+    )?;
+
+    Ok(addr)
+}
+
+/// Returns the sum of unspent transparent outputs known to be controlled by `account`
+/// as of `anchor_height`.
+pub fn get_transparent_balance<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    account: AccountId,
+    anchor_height: BlockHeight,
+) -> Result<Amount, SqliteClientError> {
+    let utxos = get_utxos(wdb, account, anchor_height)?;
+    utxos
+        .iter()
+        .map(|utxo| utxo.txout.value)
+        .fold(Some(Amount::zero()), |acc, v| acc.and_then(|a| (a + v).into()))
+        .ok_or_else(|| {
+            SqliteClientError::CorruptedData("Sum of UTXO values is out of range".to_owned())
+        })
+}
+
+/// Returns every unspent transparent output controlled by `account`, confirmed at or
+/// before `anchor_height`. A thin convenience wrapper around
+/// [`get_unspent_transparent_outputs`] for callers that only have an account on hand.
+pub fn get_utxos<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    account: AccountId,
+    anchor_height: BlockHeight,
+) -> Result<Vec<Utxo>, SqliteClientError> {
+    let addr = match get_transparent_address(wdb, account)? {
+        Some(addr) => addr,
+        None => return Ok(vec![]),
+    };
+
+    get_unspent_transparent_outputs(wdb, &addr, anchor_height)
+}
+
+/// Returns every unspent transparent output paying `address`, confirmed at or before
+/// `anchor_height`, so a caller assembling an autoshielding transaction can select them
+/// as inputs.
+pub fn get_unspent_transparent_outputs<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    address: &TransparentAddress,
+    anchor_height: BlockHeight,
+) -> Result<Vec<Utxo>, SqliteClientError> {
+    let mut stmt = wdb.conn.prepare(
+        "SELECT prevout_txid, prevout_idx, script, value_satoshis, height
+        FROM transparent_received_outputs
+        WHERE address = ?
+        AND spent_in_tx IS NULL
+        AND height <= ?",
+    )?;
+
+    let rows = stmt.query_map(
+        params![address.encode(&wdb.params), u32::from(anchor_height)],
+        |row| {
+            let txid_bytes: Vec<u8> = row.get(0)?;
+            let index: u32 = row.get(1)?;
+            let script_bytes: Vec<u8> = row.get(2)?;
+            let value: i64 = row.get(3)?;
+            let height: Option<u32> = row.get(4)?;
+            Ok((txid_bytes, index, script_bytes, value, height))
+        },
+    )?;
+
+    let mut utxos = vec![];
+    for row in rows {
+        let (txid_bytes, index, script_bytes, value, height) = row?;
+
+        let mut txid = [0u8; 32];
+        txid.copy_from_slice(&txid_bytes);
+
+        utxos.push(Utxo {
+            outpoint: OutPoint::new(txid, index),
+            txout: TxOut {
+                value: Amount::from_i64(value)
+                    .map_err(|_| SqliteClientError::CorruptedData("UTXO value out of range".to_owned()))?,
+                script_pubkey: zcash_primitives::legacy::Script(script_bytes),
+            },
+            height: height.map(BlockHeight::from),
+        });
+    }
+
+    Ok(utxos)
+}
+
+/// Records a transparent output observed paying one of our tracked addresses.
+pub fn put_received_transparent_utxo<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    address: &TransparentAddress,
+    outpoint: &OutPoint,
+    txout: &TxOut,
+    height: Option<BlockHeight>,
+) -> Result<(), SqliteClientError> {
+    wdb.conn.execute(
+        "INSERT INTO transparent_received_outputs
+            (address, prevout_txid, prevout_idx, script, value_satoshis, height)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT (prevout_txid, prevout_idx) DO UPDATE SET height = excluded.height",
+        params![
+            address.encode(&wdb.params),
+            outpoint.hash().to_vec(),
+            outpoint.n(),
+            &txout.script_pubkey.0,
+            i64::from(txout.value),
+            height.map(u32::from),
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Marks the transparent output at `outpoint` as spent by `tx_ref`, so it is excluded
+/// from future selection.
+pub fn mark_transparent_utxo_spent<P>(
+    wdb: &WalletDb<P>,
+    tx_ref: i64,
+    outpoint: &OutPoint,
+) -> Result<(), SqliteClientError> {
+    wdb.conn.execute(
+        "UPDATE transparent_received_outputs SET spent_in_tx = ?
+        WHERE prevout_txid = ? AND prevout_idx = ?",
+        params![tx_ref, outpoint.hash().to_vec(), outpoint.n()],
+    )?;
+
+    Ok(())
+}
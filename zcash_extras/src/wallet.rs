@@ -7,7 +7,7 @@ use zcash_primitives::{
     sapling::prover::TxProver,
     transaction::{
         builder::Builder,
-        components::{amount::DEFAULT_FEE, Amount},
+        components::{amount::DEFAULT_FEE, Amount, OutPoint, TxOut},
         Transaction,
     },
     zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
@@ -18,7 +18,7 @@ use zcash_client_backend::{
     address::RecipientAddress,
     data_api::{error::Error, ReceivedTransaction, SentTransaction},
     decrypt_transaction,
-    wallet::{AccountId, OvkPolicy},
+    wallet::{AccountId, OvkPolicy, SpendableNote},
 };
 
 /// Scans a [`Transaction`] for any information that can be decrypted by the accounts in
@@ -57,6 +57,162 @@ where
     }
 }
 
+/// A single payment to make as part of a [`create_spend_to_recipients`] transaction:
+/// who to pay, how much, and (for a shielded recipient) what memo to attach.
+pub struct Payment {
+    pub recipient: RecipientAddress,
+    pub value: Amount,
+    pub memo: Option<MemoBytes>,
+}
+
+/// Computes the fee a transaction of a given shape should pay, so that note selection
+/// can size its target value to the fee an actual transaction will require instead of
+/// assuming a single constant regardless of how many inputs or outputs it ends up with.
+///
+/// `create_spend_to_address`/`create_spend_to_recipients` call this twice: once before
+/// note selection, with `shielded_spends` set to the minimum of 1, to estimate how much
+/// value to target; and again afterwards, with the actual number of notes selection
+/// chose, to confirm the selected value still covers the real fee. This does not by
+/// itself change the fee the underlying [`Builder`] bakes into the transaction -- it
+/// only governs how much value note selection targets, so selection doesn't come up
+/// short of what the transaction will actually require.
+pub trait FeeRule {
+    fn fee_for(
+        &self,
+        shielded_spends: usize,
+        transparent_spends: usize,
+        shielded_outputs: usize,
+        transparent_outputs: usize,
+    ) -> Amount;
+}
+
+/// The fee rule this crate used exclusively before fee rules became pluggable: a flat
+/// [`DEFAULT_FEE`] regardless of transaction shape.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FixedFeeRule;
+
+impl FeeRule for FixedFeeRule {
+    fn fee_for(
+        &self,
+        _shielded_spends: usize,
+        _transparent_spends: usize,
+        _shielded_outputs: usize,
+        _transparent_outputs: usize,
+    ) -> Amount {
+        DEFAULT_FEE
+    }
+}
+
+/// Decides which of the candidate spendable notes returned by
+/// [`WalletRead::get_spendable_notes`] a transaction should actually spend, given how
+/// much value it needs to cover.
+///
+/// Like [`FeeRule`], this only governs what note selection chooses to spend -- the
+/// [`Builder`] still computes and attaches the actual change output, so a `ChangeStrategy`
+/// influences the size of that change (by choosing which notes contribute to it) without
+/// being able to dictate the output itself.
+///
+/// [`WalletRead::get_spendable_notes`]: crate::WalletRead::get_spendable_notes
+pub trait ChangeStrategy {
+    /// Chooses a subset of `candidates` whose combined value covers `target_value`.
+    /// Returns `None` if no subset of `candidates` is sufficient.
+    fn select_notes(
+        &self,
+        candidates: Vec<SpendableNote>,
+        target_value: Amount,
+    ) -> Option<Vec<SpendableNote>>;
+}
+
+/// Spends every candidate note, regardless of how much change that leaves.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpendAllNotes;
+
+impl ChangeStrategy for SpendAllNotes {
+    fn select_notes(
+        &self,
+        candidates: Vec<SpendableNote>,
+        target_value: Amount,
+    ) -> Option<Vec<SpendableNote>> {
+        let total: Amount = candidates.iter().map(|n| n.note_value).sum();
+        if total < target_value {
+            None
+        } else {
+            Some(candidates)
+        }
+    }
+}
+
+/// The order in which [`GreedyChangeStrategy`] considers candidate notes.
+#[derive(Clone, Copy, Debug)]
+pub enum NoteSelectionOrder {
+    /// Spend the smallest notes first, consolidating dust into the transaction.
+    SmallestFirst,
+    /// Spend the largest notes first, minimizing the number of notes spent.
+    LargestFirst,
+}
+
+/// Greedily accumulates notes (in `order`) until `target_value` is covered, then continues
+/// pulling in one more note at a time for as long as doing so would otherwise leave nonzero
+/// change smaller than `dust_threshold` -- avoiding the creation of an uneconomical change
+/// note. Change of exactly zero is accepted, since it creates no change note.
+#[derive(Clone, Copy, Debug)]
+pub struct GreedyChangeStrategy {
+    pub order: NoteSelectionOrder,
+    pub dust_threshold: Amount,
+}
+
+impl ChangeStrategy for GreedyChangeStrategy {
+    fn select_notes(
+        &self,
+        mut candidates: Vec<SpendableNote>,
+        target_value: Amount,
+    ) -> Option<Vec<SpendableNote>> {
+        match self.order {
+            NoteSelectionOrder::SmallestFirst => {
+                candidates.sort_by(|a, b| a.note_value.cmp(&b.note_value))
+            }
+            NoteSelectionOrder::LargestFirst => {
+                candidates.sort_by(|a, b| b.note_value.cmp(&a.note_value))
+            }
+        }
+
+        let values: Vec<Amount> = candidates.iter().map(|n| n.note_value).collect();
+        let count = greedy_select_count(&values, target_value, self.dust_threshold)?;
+        candidates.truncate(count);
+        Some(candidates)
+    }
+}
+
+/// Pure by-value core of [`GreedyChangeStrategy::select_notes`]: given candidate values
+/// already sorted into the order `select_notes` considers them, decides how many (a
+/// prefix of `sorted_values`) to select. Separated out from `select_notes` so its
+/// dust-avoidance and stopping behavior can be unit-tested without constructing real
+/// [`SpendableNote`]s.
+fn greedy_select_count(
+    sorted_values: &[Amount],
+    target_value: Amount,
+    dust_threshold: Amount,
+) -> Option<usize> {
+    let mut total = Amount::zero();
+    let mut count = 0;
+    for value in sorted_values {
+        if total >= target_value {
+            let change = total - target_value;
+            if change == Amount::zero() || change >= dust_threshold {
+                break;
+            }
+        }
+        total += *value;
+        count += 1;
+    }
+
+    if total < target_value {
+        None
+    } else {
+        Some(count)
+    }
+}
+
 #[allow(clippy::needless_doctest_main)]
 /// Creates a transaction paying the specified address from the given account.
 ///
@@ -138,13 +294,15 @@ where
 ///     Amount::from_u64(1).unwrap(),
 ///     None,
 ///     OvkPolicy::Sender,
+///     &FixedFeeRule::default(),
+///     &SpendAllNotes::default(),
 /// )?;
 ///
 /// # Ok(())
 /// # }
 /// ```
 #[allow(clippy::too_many_arguments)]
-pub async fn create_spend_to_address<E, N, P, D, R>(
+pub async fn create_spend_to_address<E, N, P, D, R, FR, CS>(
     wallet_db: &mut D,
     params: &P,
     prover: impl TxProver,
@@ -154,6 +312,8 @@ pub async fn create_spend_to_address<E, N, P, D, R>(
     value: Amount,
     memo: Option<MemoBytes>,
     ovk_policy: OvkPolicy,
+    fee_rule: &FR,
+    change_strategy: &CS,
 ) -> Result<R, E>
 where
     N: Display,
@@ -161,6 +321,8 @@ where
     P: consensus::Parameters + Clone,
     R: Copy + Debug,
     D: WalletWrite<Error = E, TxRef = R>,
+    FR: FeeRule,
+    CS: ChangeStrategy,
 {
     // Check that the ExtendedSpendingKey we have been given corresponds to the
     // ExtendedFullViewingKey for the account we are spending from.
@@ -182,13 +344,30 @@ where
         .await
         .and_then(|x| x.ok_or_else(|| Error::ScanRequired.into()))?;
 
-    let target_value = value + DEFAULT_FEE;
-    let spendable_notes = wallet_db
-        .select_spendable_notes(account, target_value, anchor_height)
-        .await?;
+    let (shielded_outputs, transparent_outputs) = match to {
+        RecipientAddress::Shielded(_) => (1, 0),
+        RecipientAddress::Transparent(_) => (0, 1),
+    };
 
-    // Confirm we were able to select sufficient value
-    let selected_value = spendable_notes.iter().map(|n| n.note_value).sum();
+    // Before selection, estimate the fee assuming the smallest transaction shape
+    // possible (a single shielded input), so we know how much extra value to target.
+    let provisional_fee = fee_rule.fee_for(1, 0, shielded_outputs, transparent_outputs);
+    let target_value = value + provisional_fee;
+    // Fetch every spendable note, unfiltered, so `change_strategy` alone decides which
+    // ones this transaction spends -- a SQL-side cutoff here would hide candidates (e.g.
+    // dust) from it before it gets a say.
+    let candidate_notes = wallet_db.get_spendable_notes(account, anchor_height).await?;
+    let candidate_value = candidate_notes.iter().map(|n| n.note_value).sum();
+
+    let selected_notes = change_strategy
+        .select_notes(candidate_notes, target_value)
+        .ok_or_else(|| Error::InsufficientBalance(candidate_value, target_value).into())?;
+
+    // Now that the real number of inputs is known, confirm the selected value still
+    // covers the fee such a transaction will actually require.
+    let fee = fee_rule.fee_for(selected_notes.len(), 0, shielded_outputs, transparent_outputs);
+    let target_value = value + fee;
+    let selected_value = selected_notes.iter().map(|n| n.note_value).sum();
     if selected_value < target_value {
         return Err(E::from(Error::InsufficientBalance(
             selected_value,
@@ -198,7 +377,7 @@ where
 
     // Create the transaction
     let mut builder = Builder::new(params.clone(), height);
-    for selected in spendable_notes {
+    for selected in selected_notes {
         let from = extfvk
             .fvk
             .vk
@@ -263,3 +442,386 @@ where
         })
         .await
 }
+
+/// Creates a single transaction paying every recipient in `payments` from the given
+/// account, selecting enough notes to cover the total value sent plus the fee rather
+/// than generating one transaction per recipient. This lets a wallet batch several
+/// payments without risking double-spending the same notes across separate calls, and
+/// reduces the fees and on-chain footprint of doing so.
+///
+/// Returns the row index of the newly-created transaction in the `transactions` table.
+/// Every payment is recorded as its own row via [`WalletWrite::store_sent_tx`], all
+/// pointing at the same underlying transaction.
+///
+/// See [`create_spend_to_address`] for details on `ovk_policy` and the caveat against
+/// calling this in parallel with itself or with `create_spend_to_address`.
+#[allow(clippy::too_many_arguments)]
+pub async fn create_spend_to_recipients<E, N, P, D, R, FR, CS>(
+    wallet_db: &mut D,
+    params: &P,
+    prover: impl TxProver,
+    account: AccountId,
+    extsk: &ExtendedSpendingKey,
+    payments: &[Payment],
+    ovk_policy: OvkPolicy,
+    fee_rule: &FR,
+    change_strategy: &CS,
+) -> Result<R, E>
+where
+    N: Display,
+    E: From<Error<N>>,
+    P: consensus::Parameters + Clone,
+    R: Copy + Debug,
+    D: WalletWrite<Error = E, TxRef = R>,
+    FR: FeeRule,
+    CS: ChangeStrategy,
+{
+    if payments.is_empty() {
+        // `tx_ref` below is only ever set while recording a payment, so an empty list
+        // would otherwise leave nothing to return. `Error` has no variant dedicated to
+        // this; reuse `InsufficientBalance`, since with zero payments there is no amount
+        // of selected value that could ever satisfy the at-least-one-output this call
+        // requires.
+        return Err(E::from(Error::InsufficientBalance(
+            Amount::zero(),
+            Amount::from_u64(1).unwrap(),
+        )));
+    }
+
+    // Check that the ExtendedSpendingKey we have been given corresponds to the
+    // ExtendedFullViewingKey for the account we are spending from.
+    let extfvk = ExtendedFullViewingKey::from(extsk);
+    if !wallet_db.is_valid_account_extfvk(account, &extfvk).await? {
+        return Err(E::from(Error::InvalidExtSk(account)));
+    }
+
+    // Apply the outgoing viewing key policy.
+    let ovk = match ovk_policy {
+        OvkPolicy::Sender => Some(extfvk.fvk.ovk),
+        OvkPolicy::Custom(ovk) => Some(ovk),
+        OvkPolicy::Discard => None,
+    };
+
+    // Target the next block, assuming we are up-to-date.
+    let (height, anchor_height) = wallet_db
+        .get_target_and_anchor_heights()
+        .await
+        .and_then(|x| x.ok_or_else(|| Error::ScanRequired.into()))?;
+
+    let mut total_value = Amount::zero();
+    let mut shielded_outputs = 0;
+    let mut transparent_outputs = 0;
+    for payment in payments {
+        total_value += payment.value;
+        match &payment.recipient {
+            RecipientAddress::Shielded(_) => shielded_outputs += 1,
+            RecipientAddress::Transparent(_) => transparent_outputs += 1,
+        }
+    }
+
+    // Before selection, estimate the fee assuming the smallest transaction shape
+    // possible (a single shielded input), so we know how much extra value to target.
+    let provisional_fee = fee_rule.fee_for(1, 0, shielded_outputs, transparent_outputs);
+    let target_value = total_value + provisional_fee;
+
+    // Fetch every spendable note, unfiltered, so `change_strategy` alone decides which
+    // ones this transaction spends -- a SQL-side cutoff here would hide candidates (e.g.
+    // dust) from it before it gets a say.
+    let candidate_notes = wallet_db.get_spendable_notes(account, anchor_height).await?;
+    let candidate_value = candidate_notes.iter().map(|n| n.note_value).sum();
+
+    let selected_notes = change_strategy
+        .select_notes(candidate_notes, target_value)
+        .ok_or_else(|| Error::InsufficientBalance(candidate_value, target_value).into())?;
+
+    // Now that the real number of inputs is known, confirm the selected value still
+    // covers the fee such a transaction will actually require.
+    let fee = fee_rule.fee_for(selected_notes.len(), 0, shielded_outputs, transparent_outputs);
+    let target_value = total_value + fee;
+    let selected_value = selected_notes.iter().map(|n| n.note_value).sum();
+    if selected_value < target_value {
+        return Err(E::from(Error::InsufficientBalance(
+            selected_value,
+            target_value,
+        )));
+    }
+
+    // Create the transaction
+    let mut builder = Builder::new(params.clone(), height);
+    for selected in selected_notes {
+        let from = extfvk
+            .fvk
+            .vk
+            .to_payment_address(selected.diversifier)
+            .unwrap(); //DiversifyHash would have to unexpectedly return the zero point for this to be None
+
+        let note = from
+            .create_note(selected.note_value.into(), selected.rseed)
+            .unwrap();
+
+        let merkle_path = selected.witness.path().expect("the tree is not empty");
+
+        builder
+            .add_sapling_spend(extsk.clone(), selected.diversifier, note, merkle_path)
+            .map_err(Error::Builder)?;
+    }
+
+    for payment in payments {
+        match &payment.recipient {
+            RecipientAddress::Shielded(to) => {
+                builder.add_sapling_output(ovk, to.clone(), payment.value, payment.memo.clone())
+            }
+            RecipientAddress::Transparent(to) => builder.add_transparent_output(to, payment.value),
+        }
+        .map_err(Error::Builder)?;
+    }
+
+    let consensus_branch_id = BranchId::for_height(params, height);
+    let (tx, tx_metadata) = builder
+        .build(consensus_branch_id, &prover)
+        .map_err(Error::Builder)?;
+
+    // Automatically decrypt and store any outputs sent to our wallet, including change.
+    // This uses our viewing keys to find any outputs we can decrypt, creates decrypted
+    // note data for spendability, and saves them to the wallet database.
+    decrypt_and_store_transaction(params, wallet_db, &tx).await?;
+
+    // Record every payment against the transaction just built, looking up where each
+    // output ended up. Sapling outputs are shuffled by the builder, so the index of the
+    // n-th one we added is looked up via `tx_metadata`. Transparent outputs are not
+    // shuffled and the builder adds no transparent outputs of its own (there is no
+    // transparent change), so the n-th transparent payment we added is simply the n-th
+    // entry of `tx.vout`.
+    let mut sapling_outputs_added = 0;
+    let mut transparent_outputs_added = 0;
+    let mut tx_ref = None;
+    for payment in payments {
+        let output_index = match &payment.recipient {
+            RecipientAddress::Shielded(_) => {
+                let idx = sapling_outputs_added;
+                sapling_outputs_added += 1;
+                tx_metadata
+                    .output_index(idx)
+                    .expect("output should exist in the transaction")
+            }
+            RecipientAddress::Transparent(_) => {
+                let idx = transparent_outputs_added;
+                transparent_outputs_added += 1;
+                idx
+            }
+        };
+
+        tx_ref = Some(
+            wallet_db
+                .store_sent_tx(&SentTransaction {
+                    tx: &tx,
+                    created: time::OffsetDateTime::now_utc(),
+                    output_index,
+                    account,
+                    recipient_address: &payment.recipient,
+                    value: payment.value,
+                    memo: payment.memo.clone(),
+                })
+                .await?,
+        );
+    }
+
+    // `payments` was checked non-empty above, so the loop ran at least once.
+    Ok(tx_ref.expect("create_spend_to_recipients requires at least one payment"))
+}
+
+/// Extension of [`WalletWrite`] for backends that can mark a set of transparent outpoints
+/// spent atomically with recording a sent transaction -- i.e. in the same database
+/// transaction, rather than as a separate write afterwards. [`shield_transparent_funds`]
+/// requires this so a crash between the two writes can never leave a swept UTXO looking
+/// unspent (and therefore selectable again by a later autoshielding sweep).
+#[async_trait::async_trait]
+pub trait ShieldingWalletWrite: WalletWrite {
+    /// Like [`WalletWrite::store_sent_tx`], but also marks every outpoint in
+    /// `utxos_spent` as spent by the resulting transaction, as part of the same write.
+    async fn store_sent_tx_spending_utxos(
+        &mut self,
+        sent_tx: &SentTransaction,
+        utxos_spent: &[OutPoint],
+    ) -> Result<Self::TxRef, Self::Error>;
+}
+
+/// Sweeps a set of transparent UTXOs the wallet controls into a single shielded output
+/// at the account's default address, in one transaction -- the autoshielding companion
+/// to [`create_spend_to_address`].
+///
+/// Every entry in `utxos` is spent, using `transparent_sk` as the spending key for all
+/// of them; unlike `create_spend_to_address` there is no note selection to perform, so
+/// no [`ChangeStrategy`] is needed. `transparent_sk` must be the secret key for the
+/// single transparent address an account controls, and `utxos` should be the outputs
+/// received at that address. Neither lookup is part of this crate's [`WalletRead`] or
+/// [`WalletWrite`] traits; finding them is the caller's responsibility (a backend may
+/// offer its own queries for them) -- mirroring how `create_spend_to_recipients` takes an
+/// already-assembled `payments` list rather than querying for recipients itself.
+///
+/// [`WalletRead`]: crate::WalletRead
+#[allow(clippy::too_many_arguments)]
+pub async fn shield_transparent_funds<E, N, P, D, R, FR>(
+    wallet_db: &mut D,
+    params: &P,
+    prover: impl TxProver,
+    account: AccountId,
+    extsk: &ExtendedSpendingKey,
+    transparent_sk: &secp256k1::SecretKey,
+    utxos: &[(OutPoint, TxOut)],
+    memo: Option<MemoBytes>,
+    fee_rule: &FR,
+) -> Result<R, E>
+where
+    N: Display,
+    E: From<Error<N>>,
+    P: consensus::Parameters + Clone,
+    R: Copy + Debug,
+    D: ShieldingWalletWrite<Error = E, TxRef = R>,
+    FR: FeeRule,
+{
+    // Check that the ExtendedSpendingKey we have been given corresponds to the
+    // ExtendedFullViewingKey for the account we are spending from.
+    let extfvk = ExtendedFullViewingKey::from(extsk);
+    if !wallet_db.is_valid_account_extfvk(account, &extfvk).await? {
+        return Err(E::from(Error::InvalidExtSk(account)));
+    }
+
+    // Target the next block, assuming we are up-to-date.
+    let (height, _) = wallet_db
+        .get_target_and_anchor_heights()
+        .await
+        .and_then(|x| x.ok_or_else(|| Error::ScanRequired.into()))?;
+
+    let total_value: Amount = utxos.iter().map(|(_, txout)| txout.value).sum();
+    let fee = fee_rule.fee_for(0, utxos.len(), 1, 0);
+    let shielded_value = total_value - fee;
+    if utxos.is_empty() || shielded_value <= Amount::zero() {
+        return Err(E::from(Error::InsufficientBalance(total_value, fee)));
+    }
+
+    // Create the transaction
+    let mut builder = Builder::new(params.clone(), height);
+    for (outpoint, txout) in utxos {
+        builder
+            .add_transparent_input(*transparent_sk, outpoint.clone(), txout.clone())
+            .map_err(Error::Builder)?;
+    }
+
+    let ovk = extfvk.fvk.ovk;
+    let to = extfvk.default_address().unwrap().1; // the all-zero diversifier index would have to unexpectedly fail for this to panic
+    builder
+        .add_sapling_output(Some(ovk), to.clone(), shielded_value, memo.clone())
+        .map_err(Error::Builder)?;
+
+    let consensus_branch_id = BranchId::for_height(params, height);
+    let (tx, tx_metadata) = builder
+        .build(consensus_branch_id, &prover)
+        .map_err(Error::Builder)?;
+
+    // Automatically decrypt and store any outputs sent to our wallet, including change.
+    decrypt_and_store_transaction(params, wallet_db, &tx).await?;
+
+    let output_index = match tx_metadata.output_index(0) {
+        Some(idx) => idx,
+        None => panic!("Output 0 should exist in the transaction"),
+    };
+
+    let utxos_spent: Vec<OutPoint> = utxos.iter().map(|(outpoint, _)| outpoint.clone()).collect();
+    wallet_db
+        .store_sent_tx_spending_utxos(
+            &SentTransaction {
+                tx: &tx,
+                created: time::OffsetDateTime::now_utc(),
+                output_index,
+                account,
+                recipient_address: &RecipientAddress::Shielded(to),
+                value: shielded_value,
+                memo,
+            },
+            &utxos_spent,
+        )
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{greedy_select_count, FeeRule, FixedFeeRule, NoteSelectionOrder};
+    use zcash_primitives::transaction::components::{amount::DEFAULT_FEE, Amount};
+
+    #[test]
+    fn fixed_fee_rule_ignores_transaction_shape() {
+        let rule = FixedFeeRule::default();
+        assert_eq!(rule.fee_for(0, 0, 0, 0), DEFAULT_FEE);
+        assert_eq!(rule.fee_for(3, 2, 5, 1), DEFAULT_FEE);
+    }
+
+    fn amounts(values: &[i64]) -> Vec<Amount> {
+        values.iter().map(|v| Amount::from_i64(*v).unwrap()).collect()
+    }
+
+    #[test]
+    fn greedy_select_stops_as_soon_as_target_is_covered_with_no_change() {
+        let values = amounts(&[2, 3, 5]);
+        let count = greedy_select_count(&values, Amount::from_i64(5).unwrap(), Amount::zero()).unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn greedy_select_pulls_in_one_more_note_to_avoid_dust_change() {
+        // After the first two notes, change would be 1 -- below the dust threshold of 2,
+        // so a third note should be pulled in even though the target is already covered.
+        let values = amounts(&[2, 4, 10]);
+        let target = Amount::from_i64(5).unwrap();
+        let dust_threshold = Amount::from_i64(2).unwrap();
+
+        let count = greedy_select_count(&values, target, dust_threshold).unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn greedy_select_accepts_change_at_or_above_dust_threshold() {
+        // Change of exactly 2 meets (rather than falls below) the dust threshold, so
+        // selection stops without pulling in the third note.
+        let values = amounts(&[2, 5, 10]);
+        let target = Amount::from_i64(5).unwrap();
+        let dust_threshold = Amount::from_i64(2).unwrap();
+
+        let count = greedy_select_count(&values, target, dust_threshold).unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn greedy_select_returns_none_when_candidates_are_insufficient() {
+        let values = amounts(&[1, 1]);
+        assert!(greedy_select_count(&values, Amount::from_i64(5).unwrap(), Amount::zero()).is_none());
+    }
+
+    #[test]
+    fn greedy_select_order_determines_which_values_are_passed_in() {
+        // `select_notes` is responsible for sorting candidates into `order` before
+        // calling this; confirm both orderings of the same candidate set select the
+        // expected prefix once sorted accordingly.
+        let mut smallest_first = amounts(&[5, 1, 3]);
+        smallest_first.sort();
+        assert_eq!(smallest_first, amounts(&[1, 3, 5]));
+
+        let mut largest_first = amounts(&[5, 1, 3]);
+        largest_first.sort_by(|a, b| b.cmp(a));
+        assert_eq!(largest_first, amounts(&[5, 3, 1]));
+
+        let target = Amount::from_i64(4).unwrap();
+        assert_eq!(
+            greedy_select_count(&smallest_first, target, Amount::zero()),
+            Some(2),
+        );
+        assert_eq!(
+            greedy_select_count(&largest_first, target, Amount::zero()),
+            Some(1),
+        );
+
+        // Confirm the enum variants used by callers to pick one of these orderings exist.
+        let _smallest = NoteSelectionOrder::SmallestFirst;
+        let _largest = NoteSelectionOrder::LargestFirst;
+    }
+}